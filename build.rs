@@ -3,7 +3,6 @@ extern crate gl_generator;
 use gl_generator::{Api, Fallbacks, GlobalGenerator, Profile, Registry};
 use std::env;
 use std::fs::File;
-use std::io::Write;
 use std::path::Path;
 
 fn main() {
@@ -15,7 +14,7 @@ fn main() {
     if !path.exists() {
         let mut file = File::create(&path).unwrap();
 
-        Registry::new(Api::Gl, (3, 3), Profile::Compatibility, Fallbacks::All, [])
+        Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, [])
             .write_bindings(GlobalGenerator, &mut file)
             .unwrap();
     }
@@ -31,15 +30,6 @@ fn native() {
     if !target.exists() {
         std::fs::copy(&Path::new("C:\\Local\\gaemlib\\SDL2.dll"), &target).unwrap();
     }
-
-    /*
-    let source = Path::new(&out_dir).join("../../../../../content").canonicalize().unwrap();
-    let target = Path::new(&out_dir).join("../../../contentpath");
-    if !target.exists() {
-        let mut file = File::create(&target).unwrap();
-        write!(file, "{}", source.to_str().unwrap()).unwrap();
-    }
-    */
 }
 
 #[cfg(unix)]
@@ -1,13 +1,16 @@
-#version 400
+#version 330 core
 
 layout(location = 0) in vec3 vertPos;
 layout(location = 1) in vec2 texCoord;
+layout(location = 2) in vec4 vertColor;
 
 uniform mat4 viewMatrix, projMatrix;
 
 out vec2 tex_coord;
+out vec4 color;
 
 void main() {
   gl_Position = projMatrix * viewMatrix * vec4(vertPos, 1.0);
-  tex_coord = gl_Position.xy;
-}
\ No newline at end of file
+  tex_coord = texCoord;
+  color = vertColor;
+}
@@ -1,12 +1,20 @@
-#version 400
+#version 330 core
 
 layout(location = 0) out vec4 frag_color;
 
+uniform float time;
+uniform vec2 resolution;
+uniform vec2 camera;
+uniform vec3 colors;
+
 void main() {
+  // the same pattern at every resolution, scrolling slower than the map
+  vec2 coord = gl_FragCoord.xy / resolution * vec2(711.0, 400.0) +
+               vec2(camera.x, -camera.y) * 20.0;
+
   frag_color.x =
-      abs(sin(gl_FragCoord.x / 100.0) / 7.0 + tan(gl_FragCoord.z / 11.0)) * 0.3;
-  frag_color.y =
-      abs(sin(gl_FragCoord.y / 130.0) + sin(gl_FragCoord.x / 1000.0)) * 0.8;
-  frag_color.z = 0.7;
+      abs(sin(coord.x / 100.0 + time * 0.2) / 7.0 + tan(gl_FragCoord.z / 11.0)) * colors.x;
+  frag_color.y = abs(sin(coord.y / 130.0) + sin(coord.x / 1000.0)) * colors.y;
+  frag_color.z = colors.z;
   frag_color.w = 1.0;
-}
\ No newline at end of file
+}
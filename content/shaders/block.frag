@@ -1,11 +1,17 @@
+#version 330 core
+
+in vec4 color;
+
+layout(location = 0) out vec4 frag_color;
+
 void main() {
-  gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+  frag_color = color;
 
-  // gl_FragColor.x =
+  // frag_color.x =
   //     abs(sin(gl_FragCoord.x / 100.0) / 7.0 + cos(gl_FragCoord.z / 11.0)) *
   //     0.3;
-  // gl_FragColor.y =
+  // frag_color.y =
   //     abs(sin(gl_FragCoord.y / 100.0) + sin(gl_FragCoord.x / 1000.0)) * 0.5;
-  // gl_FragColor.z = 1.0;
-  // gl_FragColor.w = 1.0;
+  // frag_color.z = 1.0;
+  // frag_color.w = 1.0;
 }
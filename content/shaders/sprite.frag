@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec4 color;
+in vec2 tex_coord;
+
+layout(location = 0) out vec4 frag_color;
+
+uniform sampler2D texture1;
+
+void main() {
+  frag_color = texture(texture1, tex_coord) * color;
+}
@@ -0,0 +1,277 @@
+extern crate colored;
+
+use self::colored::Colorize;
+use roots::Roots;
+#[cfg(feature = "window")]
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Something loaded from the content directory, see `Content::load`.
+pub trait Asset: Sized + 'static {
+    /// What the asset is loaded from, usually its path in the content
+    /// directory. Loading the same key again gives the same asset.
+    type Key: Clone + Eq + Hash + Debug + 'static;
+
+    fn load(key: &Self::Key, roots: &Roots) -> Result<Self, String>;
+
+    /// Loads the asset again after one of its files changed. Keeps the old
+    /// asset if that fails.
+    fn reload(&mut self, key: &Self::Key, roots: &Roots) -> Result<(), String> {
+        *self = Self::load(key, roots)?;
+        Ok(())
+    }
+
+    /// Every file the asset was or could be loaded from, in any of the content
+    /// directories. It's reloaded when one of them changes.
+    fn files(&self, key: &Self::Key, roots: &Roots) -> Vec<PathBuf>;
+}
+
+/// Refers to an asset in `Content`. The asset is unloaded once all handles to
+/// it are dropped.
+pub struct Handle<T> {
+    id: Rc<usize>,
+    asset: PhantomData<T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle {
+            id: self.id.clone(),
+            asset: PhantomData,
+        }
+    }
+}
+
+/// All loaded assets of one type.
+pub struct Assets<T: Asset> {
+    slots: HashMap<usize, Slot<T>>,
+    /// Keeps a handle to every asset, which is how unused ones are found.
+    handles: HashMap<T::Key, Handle<T>>,
+    next_id: usize,
+}
+
+struct Slot<T: Asset> {
+    key: T::Key,
+    asset: T,
+    /// The files of the asset with the version it was loaded at.
+    files: Vec<(PathBuf, u64)>,
+}
+
+impl<T: Asset> Slot<T> {
+    fn update_files(&mut self, roots: &Roots, versions: &mut HashMap<PathBuf, u64>) {
+        self.files = self
+            .asset
+            .files(&self.key, roots)
+            .into_iter()
+            .map(|file| {
+                let version = *versions.entry(file.clone()).or_insert(0);
+                (file, version)
+            })
+            .collect();
+    }
+}
+
+impl<T: Asset> Default for Assets<T> {
+    fn default() -> Assets<T> {
+        Assets {
+            slots: HashMap::new(),
+            handles: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T: Asset> Assets<T> {
+    /// Loads the asset, or returns another handle to it if it's loaded
+    /// already. Registers its files in `versions`.
+    pub fn load(
+        &mut self,
+        key: T::Key,
+        roots: &Roots,
+        versions: &mut HashMap<PathBuf, u64>,
+    ) -> Result<Handle<T>, String> {
+        if let Some(handle) = self.handles.get(&key) {
+            return Ok(handle.clone());
+        }
+
+        println!("Loading: {:?}", key);
+        let mut slot = Slot {
+            asset: T::load(&key, roots)?,
+            key: key.clone(),
+            files: Vec::new(),
+        };
+        slot.update_files(roots, versions);
+
+        let handle = Handle {
+            id: Rc::new(self.next_id),
+            asset: PhantomData,
+        };
+        self.slots.insert(self.next_id, slot);
+        self.handles.insert(key, handle.clone());
+        self.next_id += 1;
+
+        Ok(handle)
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> &T {
+        &self.slots[&*handle.id].asset
+    }
+
+    #[cfg(feature = "window")]
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> &mut T {
+        &mut self.slots.get_mut(&*handle.id).unwrap().asset
+    }
+}
+
+/// `Assets` of any type, so `Content` can keep them together.
+pub trait Store {
+    /// Reloads the assets whose files changed and unloads the ones that aren't
+    /// used anymore.
+    fn update(&mut self, roots: &Roots, versions: &mut HashMap<PathBuf, u64>);
+
+    #[cfg(feature = "window")]
+    fn as_any(&self) -> &dyn Any;
+
+    #[cfg(feature = "window")]
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Asset> Store for Assets<T> {
+    fn update(&mut self, roots: &Roots, versions: &mut HashMap<PathBuf, u64>) {
+        let slots = &mut self.slots;
+        self.handles.retain(|key, handle| {
+            // only the handle in `handles` is left
+            if Rc::strong_count(&handle.id) == 1 {
+                println!("Unloading: {:?}", key);
+                slots.remove(&*handle.id);
+                false
+            } else {
+                true
+            }
+        });
+
+        for slot in self.slots.values_mut() {
+            let changed = slot
+                .files
+                .iter()
+                .any(|(file, version)| versions.get(file) != Some(version));
+            if !changed {
+                continue;
+            }
+
+            println!("Reloading: {:?}", slot.key);
+            if let Err(err) = slot.asset.reload(&slot.key, roots) {
+                println!("{}", err.red());
+            }
+            // also after errors, so the asset isn't loaded again until the
+            // next change
+            slot.update_files(roots, versions);
+        }
+    }
+
+    #[cfg(feature = "window")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[cfg(feature = "window")]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use options::Options;
+    use std::env;
+
+    /// Counts how often it was loaded.
+    struct Counter(usize);
+
+    impl Asset for Counter {
+        type Key = &'static str;
+
+        fn load(_: &&'static str, _: &Roots) -> Result<Counter, String> {
+            Ok(Counter(1))
+        }
+
+        fn reload(&mut self, _: &&'static str, _: &Roots) -> Result<(), String> {
+            self.0 += 1;
+            Ok(())
+        }
+
+        fn files(&self, key: &&'static str, _: &Roots) -> Vec<PathBuf> {
+            vec![PathBuf::from(key)]
+        }
+    }
+
+    fn roots() -> Roots {
+        let options = Options {
+            content: Some(env::temp_dir()),
+            ..Options::default()
+        };
+        Roots::find(&options).unwrap()
+    }
+
+    #[test]
+    fn loads_a_key_once() {
+        let (roots, mut versions) = (roots(), HashMap::new());
+        let mut assets = Assets::<Counter>::default();
+
+        let a = assets.load("a", &roots, &mut versions).unwrap();
+        let also_a = assets.load("a", &roots, &mut versions).unwrap();
+        let b = assets.load("b", &roots, &mut versions).unwrap();
+
+        assert_eq!(a.id, also_a.id);
+        assert_ne!(a.id, b.id);
+        assert_eq!(assets.slots.len(), 2);
+        assert_eq!(assets.get(&also_a).0, 1);
+    }
+
+    #[test]
+    fn unloads_when_the_last_handle_is_dropped() {
+        let (roots, mut versions) = (roots(), HashMap::new());
+        let mut assets = Assets::<Counter>::default();
+
+        let a = assets.load("a", &roots, &mut versions).unwrap();
+        let also_a = a.clone();
+        drop(a);
+        assets.update(&roots, &mut versions);
+        assert_eq!(assets.slots.len(), 1);
+
+        drop(also_a);
+        assets.update(&roots, &mut versions);
+        assert!(assets.slots.is_empty());
+        assert!(assets.handles.is_empty());
+
+        // and loads it again the next time
+        let a = assets.load("a", &roots, &mut versions).unwrap();
+        assert_eq!(assets.get(&a).0, 1);
+    }
+
+    #[test]
+    fn reloads_when_the_version_changes() {
+        let (roots, mut versions) = (roots(), HashMap::new());
+        let mut assets = Assets::<Counter>::default();
+
+        let a = assets.load("a", &roots, &mut versions).unwrap();
+        let b = assets.load("b", &roots, &mut versions).unwrap();
+        assets.update(&roots, &mut versions);
+        assert_eq!(assets.get(&a).0, 1);
+
+        *versions.get_mut(&PathBuf::from("a")).unwrap() += 1;
+        assets.update(&roots, &mut versions);
+        assert_eq!(assets.get(&a).0, 2);
+        assert_eq!(assets.get(&b).0, 1);
+
+        // only once per change
+        assets.update(&roots, &mut versions);
+        assert_eq!(assets.get(&a).0, 2);
+    }
+}
@@ -0,0 +1,90 @@
+use map::Map;
+use rect::Rect;
+
+/// Follows the player around maps that are bigger than the screen.
+///
+/// The target can move around inside the dead zone without moving the camera,
+/// once it leaves it the camera catches up smoothly. The view never shows
+/// anything outside of the map, unless the map is smaller than the view, then
+/// it's centered.
+pub struct Camera {
+    /// Center of the view in tiles.
+    pub x: f64,
+    pub y: f64,
+    /// Size of the view in tiles.
+    pub width: f64,
+    pub height: f64,
+    /// Size of the area around the center the target can move in freely.
+    pub dead_zone: (f64, f64),
+    /// How quickly the camera catches up with the target, higher is faster.
+    pub smoothing: f64,
+}
+
+impl Camera {
+    pub fn new(width: f64, height: f64) -> Camera {
+        Camera {
+            x: width / 2.0,
+            y: height / 2.0,
+            width,
+            height,
+            dead_zone: (2.0, 1.5),
+            smoothing: 8.0,
+        }
+    }
+
+    /// The area of the map that is visible.
+    pub fn view(&self) -> Rect {
+        Rect::new(
+            self.x - self.width / 2.0,
+            self.y - self.height / 2.0,
+            self.width,
+            self.height,
+        )
+    }
+
+    /// Centers the camera on `target` right away, e.g. when a level is loaded.
+    pub fn jump_to(&mut self, target: &Rect, map: &Map) {
+        self.x = target.x + target.width / 2.0;
+        self.y = target.y + target.height / 2.0;
+        self.clamp(map);
+    }
+
+    /// Moves the camera towards `target`.
+    pub fn update(&mut self, target: &Rect, map: &Map, time_passed: f64) {
+        let goal_x = follow(self.x, target.x + target.width / 2.0, self.dead_zone.0);
+        let goal_y = follow(self.y, target.y + target.height / 2.0, self.dead_zone.1);
+
+        // frame rate independent exponential smoothing
+        let t = 1.0 - (-self.smoothing * time_passed).exp();
+        self.x += (goal_x - self.x) * t;
+        self.y += (goal_y - self.y) * t;
+
+        self.clamp(map);
+    }
+
+    fn clamp(&mut self, map: &Map) {
+        self.x = clamp_axis(self.x, self.width, map.width() as f64);
+        self.y = clamp_axis(self.y, self.height, map.height() as f64);
+    }
+}
+
+/// Where the camera at `center` has to go so `target` is inside the dead zone.
+fn follow(center: f64, target: f64, dead_zone: f64) -> f64 {
+    let half = dead_zone / 2.0;
+
+    if target > center + half {
+        target - half
+    } else if target < center - half {
+        target + half
+    } else {
+        center
+    }
+}
+
+fn clamp_axis(center: f64, view: f64, map: f64) -> f64 {
+    if view >= map {
+        map / 2.0
+    } else {
+        center.clamp(view / 2.0, map - view / 2.0)
+    }
+}
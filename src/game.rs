@@ -0,0 +1,455 @@
+use map::{Collision, Map, MoveOptions};
+use rect::Rect;
+
+/// The length of a single simulation step in seconds (120 Hz).
+pub const TIME_STEP: f64 = 1.0 / 120.0;
+
+/// Frame times above this are cut off so a long stall doesn't make the
+/// simulation try to catch up forever. Also the longest step replays and
+/// scripts can use.
+pub const MAX_FRAME_TIME: f64 = 0.25;
+
+/// Accumulates the real time between frames and hands it out in fixed
+/// fixed sized steps so the physics don't depend on the frame rate.
+#[cfg(feature = "window")]
+pub struct Timestep {
+    step: f64,
+    accumulator: f64,
+}
+
+#[cfg(feature = "window")]
+impl Timestep {
+    pub fn new(step: f64) -> Timestep {
+        Timestep {
+            step,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn advance(&mut self, frame_time: f64) {
+        self.accumulator += frame_time.min(MAX_FRAME_TIME);
+    }
+
+    /// Consumes one step from the accumulated time. Returns false if there
+    /// isn't enough time left for a full step.
+    pub fn next_step(&mut self) -> bool {
+        if self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How far the renderer is between the previous and the current step.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+}
+
+/// The input state for a single simulation step.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Input {
+    pub is_left_down: bool,
+    pub is_right_down: bool,
+    pub is_down_down: bool,
+    pub is_jump_down: bool,
+    pub is_jump_press: bool,
+}
+
+pub struct Jump<F>
+where
+    F: Fn(f64, f64, f64, f64) -> f64,
+{
+    pub max_length: f64,
+    pub max_height: f64,
+
+    pub cur_length: f64,
+    pub cur_height: f64,
+    pub is_done: bool,
+
+    pub easing: F,
+}
+
+impl<F> Jump<F>
+where
+    F: Fn(f64, f64, f64, f64) -> f64,
+{
+    pub fn new(easing: F) -> Jump<F> {
+        Jump {
+            max_height: 1.0,
+            max_length: 1.0,
+            cur_height: 0.0,
+            cur_length: 0.0,
+
+            is_done: true,
+            easing,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.is_done = true;
+    }
+
+    pub fn start(&mut self, height: f64, length: f64) {
+        self.is_done = false;
+        self.max_height = height;
+        self.max_length = length;
+        self.cur_height = 0.0;
+        self.cur_length = 0.0;
+    }
+
+    pub fn step(&mut self, time_passed: f64, is_key_down: bool) -> f64 {
+        if self.cur_length + time_passed > self.max_length {
+            // ended
+            self.stop();
+            -(self.max_height - self.cur_height)
+        } else {
+            let tmp = self.cur_height;
+            self.cur_length += time_passed;
+
+            self.cur_height = (self.easing)(self.cur_length, 0.0, self.max_height, self.max_length);
+
+            if !is_key_down {
+                self.cur_length += time_passed * 3.0;
+            }
+
+            -(self.cur_height - tmp) / time_passed * if is_key_down { 1.0 } else { 0.2 }
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.is_done
+    }
+}
+
+type Easing = fn(f64, f64, f64, f64) -> f64;
+
+fn ease_out_quad(mut t: f64, b: f64, c: f64, d: f64) -> f64 {
+    t /= d;
+    -c * (t * (t - 2.0) + b)
+}
+
+pub struct Player {
+    pub rect: Rect,
+    /// The rect before the last step, used to interpolate when rendering.
+    pub prev_rect: Rect,
+    pub dx: f64,
+    pub dy: f64,
+    pub on_floor: bool,
+    pub can_double_jump: bool,
+    pub sliding_on_left_wall: bool,
+    pub sliding_on_right_wall: bool,
+    /// Which way the sprite looks, towards the last movement or away from the
+    /// wall the player slides on.
+    pub facing_left: bool,
+    /// If the player jumped in the last step, for the jump sound.
+    pub jumped: bool,
+
+    /// The collision of the last step.
+    pub collision: Collision,
+
+    jump: Jump<Easing>,
+}
+
+impl Player {
+    pub fn new(x: f64, y: f64) -> Player {
+        let rect = Rect::new(x, y, 0.9, 0.6);
+
+        Player {
+            rect,
+            prev_rect: rect,
+            dx: 0.0,
+            dy: 0.0,
+            on_floor: false,
+            can_double_jump: false,
+            sliding_on_left_wall: false,
+            sliding_on_right_wall: false,
+            facing_left: false,
+            jumped: false,
+            collision: Collision::default(),
+            jump: Jump::new(ease_out_quad),
+        }
+    }
+
+    pub fn is_sliding_on_wall(&self) -> bool {
+        self.sliding_on_left_wall || self.sliding_on_right_wall
+    }
+
+    /// The animation for the current state of the player, see `sprite::Sheet`.
+    #[cfg(feature = "window")]
+    pub fn animation(&self) -> &'static str {
+        if self.on_floor {
+            if self.dx.abs() > 0.1 {
+                "run"
+            } else {
+                "idle"
+            }
+        } else if self.is_sliding_on_wall() {
+            "wall_slide"
+        } else {
+            "jump"
+        }
+    }
+
+    /// Puts the player back to the spawn of `map`.
+    pub fn respawn(&mut self, map: &Map) {
+        *self = Player::new(map.spawn.0, map.spawn.1);
+    }
+
+    /// The rect to render, `alpha` steps between the previous and current position.
+    #[cfg(feature = "window")]
+    pub fn interpolated_rect(&self, alpha: f64) -> Rect {
+        self.prev_rect.lerp(&self.rect, alpha)
+    }
+
+    pub fn step(&mut self, input: &Input, time_passed: f64, map: &Map) {
+        self.prev_rect = self.rect;
+
+        let on_ladder = map.any_tile(&self.rect, |tile| tile.climbable);
+        let mut drop_through = false;
+        self.jumped = false;
+
+        if input.is_jump_press && !on_ladder {
+            if self.on_floor && input.is_down_down && self.collision.floor.def().one_way {
+                drop_through = true;
+            } else if self.on_floor {
+                self.jump.start(3.0, 0.4);
+                self.jumped = true;
+            } else if self.is_sliding_on_wall() {
+                self.jump.start(2.0, 0.3);
+                self.jumped = true;
+                self.dx = if self.sliding_on_left_wall {
+                    10.0
+                } else {
+                    -10.0
+                };
+                self.can_double_jump = true;
+            } else if self.can_double_jump {
+                self.jump.start(0.0, 0.05);
+                self.can_double_jump = false;
+                self.jumped = true;
+            }
+        }
+
+        if on_ladder {
+            // climb while jump is held, slowly slide down otherwise
+            self.jump.stop();
+            self.dy = if input.is_jump_down { -4.0 } else { 2.0 };
+            self.can_double_jump = true;
+        } else if self.jump.is_done() {
+            self.dy += 40.0 * time_passed;
+            self.dy = self.dy.clamp(-20.0, 13.0);
+        } else {
+            self.dy = self.jump.step(time_passed, input.is_jump_down);
+        }
+
+        // slippery floors make turning and stopping slower
+        let friction = if self.on_floor {
+            self.collision.floor.def().friction
+        } else {
+            1.0
+        };
+
+        // left + right input:
+        let turn_speed = if self.on_floor {
+            100.0 * friction
+        } else {
+            70.0
+        };
+        if input.is_left_down {
+            self.dx -= turn_speed * time_passed;
+        }
+        if input.is_right_down {
+            self.dx += turn_speed * time_passed;
+        }
+
+        if !input.is_right_down && !input.is_left_down {
+            let slow_down_speed = if self.on_floor {
+                150.0 * friction
+            } else {
+                75.0
+            };
+            if self.dx > 0.0 {
+                self.dx = (self.dx - slow_down_speed * time_passed).max(0.0);
+            } else {
+                self.dx = (self.dx + slow_down_speed * time_passed).min(0.0);
+            }
+        }
+
+        // keep the speed along slopes the same as on flat ground
+        let max_dx = if self.on_floor {
+            10.0 * self.collision.slope.cos()
+        } else {
+            10.0
+        };
+        self.dx = self.dx.clamp(-max_dx, max_dx);
+
+        let options = MoveOptions {
+            drop_through,
+            snap_to_ground: self.on_floor && self.jump.is_done() && !on_ladder,
+        };
+        let collision = map.move_item(&mut self.rect, self.dx, self.dy, time_passed, options);
+
+        if map.any_tile(&self.rect, |tile| tile.hazard) {
+            self.respawn(map);
+            return;
+        }
+
+        // floor collision
+        self.on_floor = collision.is_on_floor();
+        if self.on_floor {
+            self.dy = 0.0;
+            self.can_double_jump = true;
+
+            let bounce = collision.floor.def().bounce;
+            if bounce > 0.0 {
+                self.jump.start(bounce, 0.4);
+            }
+        }
+
+        // ceiling collision
+        if collision.top {
+            self.dy = self.dy.max(0.0);
+            self.jump.stop();
+        }
+
+        // wall collision
+        self.sliding_on_left_wall = collision.left;
+        self.sliding_on_right_wall = collision.right;
+
+        if self.is_sliding_on_wall() {
+            self.dy = self.dy.min(4.0);
+        }
+
+        if self.sliding_on_left_wall {
+            self.facing_left = false;
+        } else if self.sliding_on_right_wall {
+            self.facing_left = true;
+        } else if self.dx != 0.0 {
+            self.facing_left = self.dx < 0.0;
+        }
+
+        self.collision = collision;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room() -> Map {
+        Map::from_rows(&[
+            "X..........X",
+            "X..........X",
+            "X..........X",
+            "X..........X",
+            "X..........X",
+            "X..........X",
+            "X..........X",
+            "XXXXXXXXXXXX",
+        ])
+    }
+
+    fn run(player: &mut Player, map: &Map, input: Input, steps: usize) {
+        for _ in 0..steps {
+            player.step(&input, TIME_STEP, map);
+        }
+    }
+
+    /// A player standing on the floor of `map`.
+    fn landed(map: &Map, x: f64) -> Player {
+        let mut player = Player::new(x, 6.0);
+        run(&mut player, map, Input::default(), 60);
+        assert!(player.on_floor);
+        player
+    }
+
+    fn jump_press() -> Input {
+        Input {
+            is_jump_down: true,
+            is_jump_press: true,
+            ..Input::default()
+        }
+    }
+
+    fn held() -> Input {
+        Input {
+            is_jump_down: true,
+            ..Input::default()
+        }
+    }
+
+    #[test]
+    fn jump_reaches_its_height() {
+        let map = room();
+        let mut player = landed(&map, 5.0);
+        let start = player.rect.y;
+
+        run(&mut player, &map, jump_press(), 1);
+        let mut apex = player.rect.y;
+        while !player.on_floor {
+            run(&mut player, &map, held(), 1);
+            apex = apex.min(player.rect.y);
+        }
+
+        let height = start - apex;
+        assert!((height - 3.0).abs() < 0.05, "jumped {} tiles", height);
+    }
+
+    #[test]
+    fn double_jump_only_once() {
+        let map = room();
+        let mut player = landed(&map, 5.0);
+
+        run(&mut player, &map, jump_press(), 1);
+        run(&mut player, &map, held(), 20);
+        assert!(!player.on_floor);
+        assert!(player.can_double_jump);
+
+        run(&mut player, &map, jump_press(), 1);
+        assert!(!player.jump.is_done());
+        assert!(!player.can_double_jump);
+
+        run(&mut player, &map, held(), 10);
+        assert!(!player.on_floor);
+        assert!(player.jump.is_done());
+        let dy = player.dy;
+        run(&mut player, &map, jump_press(), 1);
+        assert!(player.jump.is_done());
+        assert!(player.dy > dy);
+    }
+
+    #[test]
+    fn wall_jump_pushes_away_from_the_wall() {
+        let map = room();
+
+        for &(x, left) in &[(1.5, true), (9.5, false)] {
+            let mut player = landed(&map, x);
+            run(&mut player, &map, jump_press(), 1);
+
+            let towards_wall = Input {
+                is_left_down: left,
+                is_right_down: !left,
+                ..Input::default()
+            };
+            run(&mut player, &map, towards_wall, 30);
+            assert!(player.is_sliding_on_wall());
+            assert_eq!(player.sliding_on_left_wall, left);
+
+            let wall_x = player.rect.x;
+            run(&mut player, &map, jump_press(), 1);
+            assert!(player.dx.abs() > 9.0);
+            assert_eq!(player.dx > 0.0, left);
+            assert!(player.can_double_jump);
+
+            run(&mut player, &map, Input::default(), 10);
+            assert!(!player.is_sliding_on_wall());
+            if left {
+                assert!(player.rect.x > wall_x);
+            } else {
+                assert!(player.rect.x < wall_x);
+            }
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::path::{Path, PathBuf};
+
+/// A shader with its `#include "file.glsl"` directives replaced by the files,
+/// which are looked up in the include directories (`shaders` in every content
+/// directory), the last one that has the file wins. Every file is only
+/// included once, later includes of the same file are skipped.
+///
+/// `defines` are added as `#define NAME VALUE` right after the `#version`
+/// line, or at the top if there is none.
+pub struct Source {
+    pub text: String,
+    /// Every file the source was made from, the shader itself first.
+    pub files: Vec<PathBuf>,
+    /// For each line of `text`, the index into `files` and the line number in
+    /// that file, starting at 1.
+    pub lines: Vec<(usize, usize)>,
+}
+
+pub fn preprocess(
+    path: &Path,
+    include_dirs: &[PathBuf],
+    defines: &[(String, String)],
+) -> Result<Source, String> {
+    let mut source = Source {
+        text: String::new(),
+        files: Vec::new(),
+        lines: Vec::new(),
+    };
+
+    include(&mut source, path, include_dirs, defines)?;
+
+    // no #version, the defines go at the top
+    if !has_version(&source.text) {
+        let mut text = String::new();
+        for (name, value) in defines {
+            text.push_str(&format!("#define {} {}\n", name, value));
+            source.lines.insert(0, (0, 1));
+        }
+        source.text.insert_str(0, &text);
+    }
+
+    Ok(source)
+}
+
+fn include(
+    source: &mut Source,
+    path: &Path,
+    include_dirs: &[PathBuf],
+    defines: &[(String, String)],
+) -> Result<(), String> {
+    let path = path
+        .canonicalize()
+        .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+    if source.files.contains(&path) {
+        return Ok(());
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+    let file = source.files.len();
+    source.files.push(path.clone());
+
+    for (i, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.trim().splitn(2, char::is_whitespace).collect();
+
+        match words.as_slice() {
+            ["#include", name] => {
+                let name = name.trim();
+                if name.len() < 2 || !name.starts_with('"') || !name.ends_with('"') {
+                    Err(format!(
+                        "Invalid #include on line {} of {}, use #include \"file.glsl\"",
+                        i + 1,
+                        path.display()
+                    ))?
+                }
+
+                let name = &name[1..name.len() - 1];
+                let found = include_dirs
+                    .iter()
+                    .rev()
+                    .map(|dir| dir.join(name))
+                    .find(|file| file.exists());
+                let file = match found {
+                    Some(file) => file,
+                    None => include_dirs
+                        .first()
+                        .map_or(PathBuf::from(name), |dir| dir.join(name)),
+                };
+
+                include(source, &file, include_dirs, defines).map_err(|err| {
+                    format!("{}, included on line {} of {}", err, i + 1, path.display())
+                })?;
+            }
+            ["#version", ..] if file == 0 => {
+                source.push(line, file, i + 1);
+                for (name, value) in defines {
+                    source.push(&format!("#define {} {}", name, value), file, i + 1);
+                }
+            }
+            _ => source.push(line, file, i + 1),
+        }
+    }
+
+    Ok(())
+}
+
+fn has_version(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.trim_start().starts_with("#version"))
+}
+
+impl Source {
+    /// Source code that isn't loaded from a file, `name` is shown in its
+    /// errors instead of a path.
+    pub fn new(name: &str, text: &str) -> Source {
+        let mut source = Source {
+            text: String::new(),
+            files: vec![PathBuf::from(name)],
+            lines: Vec::new(),
+        };
+        for (i, line) in text.lines().enumerate() {
+            source.push(line, 0, i + 1);
+        }
+
+        source
+    }
+
+    /// Rewrites a compiler log so that line references like `0(12)` (Nvidia)
+    /// or `0:12` (Mesa, AMD, Intel, Apple) point to the file and line the code
+    /// came from, and adds the offending line below each message.
+    pub fn map_log(&self, log: &str) -> String {
+        let mut mapped = String::new();
+
+        for message in log.lines().filter(|line| !line.trim().is_empty()) {
+            let location = find_line_ref(message).and_then(|(start, end, line)| {
+                self.lines
+                    .get(line.wrapping_sub(1))
+                    .map(|&(file, line_number)| (start, end, file, line_number, line))
+            });
+
+            match location {
+                Some((start, end, file, line_number, line)) => {
+                    mapped.push_str(&message[..start]);
+                    mapped.push_str(&format!("{}:{}", self.files[file].display(), line_number));
+                    mapped.push_str(&message[end..]);
+                    mapped.push('\n');
+
+                    if let Some(code) = self.text.lines().nth(line - 1) {
+                        mapped.push_str(&format!("    {}\n", code.trim()));
+                    }
+                }
+                None => {
+                    mapped.push_str(message);
+                    mapped.push('\n');
+                }
+            }
+        }
+
+        mapped
+    }
+
+    fn push(&mut self, line: &str, file: usize, line_number: usize) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.lines.push((file, line_number));
+    }
+}
+
+/// Finds `<string>(<line>)` or `<string>:<line>` in a log message and returns
+/// where it is and the line.
+fn find_line_ref(message: &str) -> Option<(usize, usize, usize)> {
+    let bytes = message.as_bytes();
+    let digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    for start in 0..bytes.len() {
+        // only whole numbers, not the end of something like `C0000`
+        if !bytes[start].is_ascii_digit()
+            || start > 0 && (bytes[start - 1].is_ascii_alphanumeric() || bytes[start - 1] == b'_')
+        {
+            continue;
+        }
+
+        let string_end = start + digits(start);
+        let (open, close) = match bytes.get(string_end) {
+            Some(b'(') => (string_end + 1, Some(b')')),
+            Some(b':') => (string_end + 1, None),
+            _ => continue,
+        };
+
+        let line_len = digits(open);
+        if line_len == 0 {
+            continue;
+        }
+        let line = message[open..open + line_len].parse().ok()?;
+
+        let end = match close {
+            Some(close) if bytes.get(open + line_len) == Some(&close) => open + line_len + 1,
+            Some(_) => continue,
+            None => open + line_len,
+        };
+
+        return Some((start, end, line));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// A directory in the temp directory with `files`, given as names and
+    /// contents.
+    fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gaem-glsl-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn includes_from_the_last_dir_that_has_the_file() {
+        let base = dir(
+            "base",
+            &[
+                ("a.frag", "#include \"x.glsl\"\n#include \"y.glsl\"\nmain\n"),
+                ("x.glsl", "base x\n"),
+                ("y.glsl", "base y\n"),
+            ],
+        );
+        let mod_dir = dir("mod", &[("y.glsl", "mod y\n")]);
+
+        let dirs = [base.clone(), mod_dir.clone()];
+        let source = preprocess(&base.join("a.frag"), &dirs, &[]).unwrap();
+
+        assert_eq!(source.text, "base x\nmod y\nmain\n");
+        assert_eq!(
+            source.files,
+            vec![
+                base.join("a.frag"),
+                base.join("x.glsl"),
+                mod_dir.join("y.glsl")
+            ]
+        );
+        assert_eq!(source.lines, vec![(1, 1), (2, 1), (0, 3)]);
+    }
+
+    #[test]
+    fn includes_files_once() {
+        let dir = dir(
+            "once",
+            &[
+                (
+                    "a.frag",
+                    "#include \"common.glsl\"\n#include \"common.glsl\"\nmain\n",
+                ),
+                ("common.glsl", "#include \"common.glsl\"\ncommon\n"),
+            ],
+        );
+
+        let dirs = [dir.clone()];
+        let source = preprocess(&dir.join("a.frag"), &dirs, &[]).unwrap();
+        assert_eq!(source.text, "common\nmain\n");
+        assert_eq!(source.files.len(), 2);
+    }
+
+    #[test]
+    fn rejects_missing_and_invalid_includes() {
+        let dir = dir(
+            "invalid",
+            &[
+                ("missing.frag", "main\n#include \"missing.glsl\"\n"),
+                ("invalid.frag", "#include <common.glsl>\n"),
+            ],
+        );
+        let dirs = [dir.clone()];
+
+        let err = preprocess(&dir.join("missing.frag"), &dirs, &[])
+            .err()
+            .unwrap();
+        assert!(err.contains("missing.glsl"), "{}", err);
+        assert!(err.contains("included on line 2 of"), "{}", err);
+
+        let err = preprocess(&dir.join("invalid.frag"), &dirs, &[])
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Invalid #include on line 1"), "{}", err);
+    }
+
+    #[test]
+    fn puts_defines_after_the_version() {
+        let dir = dir(
+            "defines",
+            &[
+                ("version.frag", "// comment\n#version 330 core\nmain\n"),
+                ("no_version.frag", "main\n"),
+            ],
+        );
+        let defines = [
+            ("A".to_owned(), "1".to_owned()),
+            ("B".to_owned(), "x y".to_owned()),
+        ];
+
+        let source = preprocess(&dir.join("version.frag"), &[], &defines).unwrap();
+        assert_eq!(
+            source.text,
+            "// comment\n#version 330 core\n#define A 1\n#define B x y\nmain\n"
+        );
+        assert_eq!(source.lines, vec![(0, 1), (0, 2), (0, 2), (0, 2), (0, 3)]);
+
+        let source = preprocess(&dir.join("no_version.frag"), &[], &defines).unwrap();
+        assert_eq!(source.text, "#define A 1\n#define B x y\nmain\n");
+        assert_eq!(source.lines, vec![(0, 1), (0, 1), (0, 1)]);
+    }
+
+    /// `main.frag` with an include and a define in a directory called `name`,
+    /// see the comments for where each line comes from.
+    fn shader(name: &str) -> (PathBuf, Source) {
+        let dir = dir(
+            name,
+            &[
+                (
+                    "main.frag",
+                    "#version 330 core\n#include \"common.glsl\"\nvoid main() {\n  broken\n}\n",
+                ),
+                ("common.glsl", "float a;\nfloat b;\n"),
+            ],
+        );
+        let dirs = [dir.clone()];
+        let defines = [("DEBUG".to_owned(), "1".to_owned())];
+
+        // 1: #version, 2: #define DEBUG, 3-4: common.glsl, 5-7: main.frag 3-5
+        let source = preprocess(&dir.join("main.frag"), &dirs, &defines).unwrap();
+        (dir, source)
+    }
+
+    #[test]
+    fn maps_nvidia_logs() {
+        let (dir, source) = shader("nvidia");
+
+        let log = source.map_log("0(6) : error C0000: syntax error, unexpected '}'\n");
+        assert_eq!(
+            log,
+            format!(
+                "{}:4 : error C0000: syntax error, unexpected '}}'\n    broken\n",
+                dir.join("main.frag").display()
+            )
+        );
+    }
+
+    #[test]
+    fn maps_mesa_logs() {
+        let (dir, source) = shader("mesa");
+
+        let log =
+            source.map_log("0:4(7): error: `b' redeclared\nERROR: 0:2: 'DEBUG' : redefined\n");
+        assert_eq!(
+            log,
+            format!(
+                "{}:2(7): error: `b' redeclared\n    float b;\nERROR: {}:1: 'DEBUG' : redefined\n    #define DEBUG 1\n",
+                dir.join("common.glsl").display(),
+                dir.join("main.frag").display()
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_messages_without_known_lines() {
+        let (_, source) = shader("unknown");
+
+        let log = "error C0000: no line\n0(99) : warning: past the end\n\nlinker error\n";
+        assert_eq!(
+            source.map_log(log),
+            "error C0000: no line\n0(99) : warning: past the end\nlinker error\n"
+        );
+    }
+
+    #[test]
+    fn maps_built_in_sources() {
+        let source = Source::new("plain vertex shader", "a\nb\n");
+
+        assert_eq!(
+            source.map_log("0(2) : error"),
+            "plain vertex shader:2 : error\n    b\n"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use game::{Input, Player, MAX_FRAME_TIME, TIME_STEP};
+use map::Map;
+use options::Options;
+use replay::Replay;
+use roots::Roots;
+use std::path::Path;
+
+/// A scripted run of the game without a window. Scripts are plain text with
+/// one command per line, `#` starts a comment:
+///
+/// ```text
+/// level levels/a.level   # switch the level and respawn (default: --level)
+/// spawn 1 0              # place the player (default: the level's spawn)
+/// dt 0.01                # step length, at most 0.25 (default: TIME_STEP)
+/// steps 120 right jump   # simulate 120 steps holding right and jump
+/// expect bottom          # collision flag of the last step
+/// expect !left
+/// expect x > 14          # compare x, y, dx or dy
+/// expect in goal         # the player overlaps a trigger named or typed `goal`
+/// expect !in spikes
+/// ```
+///
+/// Jump presses are generated automatically when `jump` goes from released
+/// to held between two `steps` commands.
+pub struct Script {
+    commands: Vec<(usize, Command)>,
+}
+
+enum Command {
+    Level(String),
+    Spawn(f64, f64),
+    TimeStep(f64),
+    Steps(u32, Input),
+    Expect(Expectation),
+}
+
+enum Expectation {
+    Flag(String, bool),
+    Trigger(String, bool),
+    Compare(String, String, f64),
+}
+
+const FLAGS: &[&str] = &["left", "right", "top", "bottom", "on_floor"];
+const VALUES: &[&str] = &["x", "y", "dx", "dy"];
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+
+        Script::parse(&src)
+    }
+
+    pub fn parse(src: &str) -> Result<Script, String> {
+        let mut commands = Vec::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let command = parse_command(line).map_err(|err| format!("line {}: {}", i + 1, err))?;
+            commands.push((i + 1, command));
+        }
+
+        Ok(Script { commands })
+    }
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words[0] {
+        "level" if words.len() == 2 => Ok(Command::Level(words[1].to_owned())),
+        "spawn" if words.len() == 3 => Ok(Command::Spawn(number(words[1])?, number(words[2])?)),
+        "dt" if words.len() == 2 => {
+            let dt = number(words[1])?;
+            // also rejects NaN
+            if !(dt > 0.0 && dt <= MAX_FRAME_TIME) {
+                Err(format!("dt must be between 0 and {}", MAX_FRAME_TIME))?
+            }
+
+            Ok(Command::TimeStep(dt))
+        }
+        "steps" if words.len() >= 2 => {
+            let count = words[1]
+                .parse()
+                .map_err(|_| format!("invalid step count: {}", words[1]))?;
+            let mut input = Input::default();
+
+            for key in &words[2..] {
+                match *key {
+                    "left" => input.is_left_down = true,
+                    "right" => input.is_right_down = true,
+                    "down" => input.is_down_down = true,
+                    "jump" => input.is_jump_down = true,
+                    _ => Err(format!("unknown key: {}", key))?,
+                }
+            }
+
+            Ok(Command::Steps(count, input))
+        }
+        "expect" if words.len() == 2 => {
+            let (name, value) = match words[1].strip_prefix('!') {
+                Some(name) => (name, false),
+                None => (words[1], true),
+            };
+
+            if !FLAGS.contains(&name) {
+                Err(format!("unknown flag: {}", name))?
+            }
+
+            Ok(Command::Expect(Expectation::Flag(name.to_owned(), value)))
+        }
+        "expect" if words.len() == 3 && words[1].trim_start_matches('!') == "in" => {
+            Ok(Command::Expect(Expectation::Trigger(
+                words[2].to_owned(),
+                !words[1].starts_with('!'),
+            )))
+        }
+        "expect" if words.len() == 4 => {
+            if !VALUES.contains(&words[1]) {
+                Err(format!("unknown value: {}", words[1]))?
+            }
+            if !["<", ">", "<=", ">=", "=="].contains(&words[2]) {
+                Err(format!("unknown operator: {}", words[2]))?
+            }
+
+            Ok(Command::Expect(Expectation::Compare(
+                words[1].to_owned(),
+                words[2].to_owned(),
+                number(words[3])?,
+            )))
+        }
+        _ => Err(format!("invalid command: {}", line)),
+    }
+}
+
+fn number(word: &str) -> Result<f64, String> {
+    word.parse()
+        .map_err(|_| format!("invalid number: {}", word))
+}
+
+/// Runs the script or replay given in `options`. Fails if it can't be loaded
+/// or any of the script's expectations don't hold.
+pub fn run(options: &Options) -> Result<(), String> {
+    let roots = Roots::find(options)?;
+
+    let (player, failures) = if let Some(ref path) = options.script {
+        let map = load_level(&roots, &options.level)?;
+        run_script(&Script::load(path)?, map, &roots)?
+    } else if let Some(ref path) = options.replay {
+        let replay = Replay::load(path)?;
+
+        (play(&replay, &load_level(&roots, &replay.level)?), 0)
+    } else {
+        Err("--headless needs either --script or --replay")?
+    };
+
+    println!("rect: {:?}", player.rect);
+    println!("collision: {:?}", player.collision);
+
+    if failures > 0 {
+        Err(format!("{} expectation(s) failed", failures))?
+    }
+
+    Ok(())
+}
+
+fn load_level(roots: &Roots, level: &str) -> Result<Map, String> {
+    Map::load(&roots.path(level))
+}
+
+/// Plays back `replay` and returns the player after the last step.
+pub fn play(replay: &Replay, map: &Map) -> Player {
+    let mut player = Player::new(replay.spawn.0, replay.spawn.1);
+
+    for input in &replay.inputs {
+        player.step(input, replay.time_step, map);
+    }
+
+    player
+}
+
+/// Returns the player after the last command and the number of failed
+/// expectations.
+fn run_script(script: &Script, mut map: Map, roots: &Roots) -> Result<(Player, u32), String> {
+    let mut player = Player::new(map.spawn.0, map.spawn.1);
+    let mut time_step = TIME_STEP;
+    let mut was_jump_down = false;
+    let mut failures = 0;
+
+    for (line, command) in &script.commands {
+        match command {
+            Command::Level(level) => {
+                map = load_level(roots, level)?;
+                player.respawn(&map);
+            }
+            Command::Spawn(x, y) => {
+                player = Player::new(*x, *y);
+            }
+            Command::TimeStep(dt) => time_step = *dt,
+            Command::Steps(count, held) => {
+                for _ in 0..*count {
+                    let input = Input {
+                        is_jump_press: held.is_jump_down && !was_jump_down,
+                        ..*held
+                    };
+                    was_jump_down = held.is_jump_down;
+
+                    player.step(&input, time_step, &map);
+                }
+            }
+            Command::Expect(expectation) => {
+                if let Err(err) = check(expectation, &player, &map) {
+                    println!("line {}: expectation failed: {}", line, err);
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    Ok((player, failures))
+}
+
+fn check(expectation: &Expectation, player: &Player, map: &Map) -> Result<(), String> {
+    match expectation {
+        Expectation::Flag(name, expected) => {
+            let collision = &player.collision;
+            let actual = match name.as_str() {
+                "left" => collision.left,
+                "right" => collision.right,
+                "top" => collision.top,
+                "bottom" => collision.bottom,
+                _ => player.on_floor,
+            };
+
+            if actual != *expected {
+                Err(format!("{} is {}", name, actual))?
+            }
+        }
+        Expectation::Trigger(name, expected) => {
+            let actual = map
+                .triggers_at(&player.rect)
+                .any(|trigger| trigger.name == *name || trigger.kind == *name);
+
+            if actual != *expected {
+                Err(format!("in {} is {}", name, actual))?
+            }
+        }
+        Expectation::Compare(name, op, expected) => {
+            let actual = match name.as_str() {
+                "x" => player.rect.x,
+                "y" => player.rect.y,
+                "dx" => player.dx,
+                _ => player.dy,
+            };
+
+            let ok = match op.as_str() {
+                "<" => actual < *expected,
+                ">" => actual > *expected,
+                "<=" => actual <= *expected,
+                ">=" => actual >= *expected,
+                _ => (actual - expected).abs() < 1e-6,
+            };
+
+            if !ok {
+                Err(format!(
+                    "{} is {}, expected {} {}",
+                    name, actual, op, expected
+                ))?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn scripts_pass() {
+        let options = Options {
+            content: Some(PathBuf::from("content")),
+            ..Options::default()
+        };
+        let roots = Roots::find(&options).unwrap();
+
+        for entry in fs::read_dir("content/scripts").unwrap() {
+            let path = entry.unwrap().path();
+            let script = Script::load(&path).unwrap();
+            let map = load_level(&roots, &options.level).unwrap();
+
+            let (_, failures) = run_script(&script, map, &roots).unwrap();
+            assert_eq!(failures, 0, "{} failed", path.display());
+        }
+    }
+}
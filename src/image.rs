@@ -0,0 +1,174 @@
+extern crate png;
+
+#[cfg(feature = "jpeg")]
+extern crate jpeg_decoder;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// An image decoded to 8-bit RGBA, rows from top to bottom.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Loads a PNG in any color type and bit depth, or a JPEG with the `jpeg`
+/// feature.
+pub fn load(path: &Path) -> Result<Image, String> {
+    let error = |err: &dyn std::fmt::Display| format!("{} when loading {}", err, path.display());
+    let file = File::open(path).map_err(|err| error(&err))?;
+
+    let image = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => decode_png(BufReader::new(file)),
+        Some("jpg") | Some("jpeg") => decode_jpeg(BufReader::new(file)),
+        _ => Err("unsupported image format".to_owned()),
+    };
+
+    image.map_err(|err| error(&err))
+}
+
+fn decode_png(reader: BufReader<File>) -> Result<Image, String> {
+    let mut decoder = png::Decoder::new(reader);
+    // palettes, low bit depths and tRNS chunks to 8-bit gray/RGB(A)
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let (info, mut reader) = decoder.read_info().map_err(|err| err.to_string())?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buf).map_err(|err| err.to_string())?;
+
+    let (color_type, _) = reader.output_color_type();
+    let pixels = match color_type {
+        png::ColorType::RGBA => buf,
+        png::ColorType::RGB => to_rgba(&buf, 3, |p| [p[0], p[1], p[2], 255]),
+        png::ColorType::GrayscaleAlpha => to_rgba(&buf, 2, |p| [p[0], p[0], p[0], p[1]]),
+        png::ColorType::Grayscale => to_rgba(&buf, 1, |p| [p[0], p[0], p[0], 255]),
+        png::ColorType::Indexed => Err("indexed colors weren't expanded")?,
+    };
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+#[cfg(feature = "jpeg")]
+fn decode_jpeg(reader: BufReader<File>) -> Result<Image, String> {
+    let mut decoder = jpeg_decoder::Decoder::new(reader);
+    let buf = decoder.decode().map_err(|err| err.to_string())?;
+    let info = decoder.info().ok_or("missing image info")?;
+
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => to_rgba(&buf, 3, |p| [p[0], p[1], p[2], 255]),
+        jpeg_decoder::PixelFormat::L8 => to_rgba(&buf, 1, |p| [p[0], p[0], p[0], 255]),
+        format => Err(format!("unsupported JPEG pixel format {:?}", format))?,
+    };
+
+    Ok(Image {
+        width: info.width as u32,
+        height: info.height as u32,
+        pixels,
+    })
+}
+
+#[cfg(not(feature = "jpeg"))]
+fn decode_jpeg(_: BufReader<File>) -> Result<Image, String> {
+    Err("JPEG support needs the \"jpeg\" feature".to_owned())
+}
+
+/// Converts pixels of `channels` bytes each to RGBA.
+fn to_rgba<F>(buf: &[u8], channels: usize, rgba: F) -> Vec<u8>
+where
+    F: Fn(&[u8]) -> [u8; 4],
+{
+    let mut pixels = Vec::with_capacity(buf.len() / channels * 4);
+    for pixel in buf.chunks(channels) {
+        pixels.extend_from_slice(&rgba(pixel));
+    }
+    pixels
+}
+
+/// How a texture is sampled. Set in code with `Texture::with_options`, or in
+/// a file next to the image with `.meta` added to its name, e.g.
+/// `blob.png.meta`, which wins over the code:
+///
+/// ```text
+/// filter nearest     # or linear
+/// wrap repeat        # or clamp
+/// mipmaps true
+/// premultiply true   # multiply the colors by alpha when loading
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureOptions {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub mipmaps: bool,
+    pub premultiply: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// Sharp pixels, for pixel art.
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            filter: Filter::Linear,
+            wrap: Wrap::Clamp,
+            mipmaps: false,
+            premultiply: false,
+        }
+    }
+}
+
+impl TextureOptions {
+    /// Applies the options from a `.meta` file on top of `self`.
+    pub fn parse(&self, src: &str) -> Result<TextureOptions, String> {
+        let mut options = *self;
+
+        for (i, line) in src.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = || format!("Invalid line {}: {}", i + 1, line);
+
+            match words.as_slice() {
+                [] => (),
+                [comment, ..] if comment.starts_with('#') => (),
+                ["filter", "nearest"] => options.filter = Filter::Nearest,
+                ["filter", "linear"] => options.filter = Filter::Linear,
+                ["wrap", "clamp"] => options.wrap = Wrap::Clamp,
+                ["wrap", "repeat"] => options.wrap = Wrap::Repeat,
+                ["mipmaps", value] => options.mipmaps = value.parse().map_err(|_| error())?,
+                ["premultiply", value] => {
+                    options.premultiply = value.parse().map_err(|_| error())?
+                }
+                _ => Err(error())?,
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+impl Image {
+    /// Multiplies the colors by alpha, for `gl::ONE, gl::ONE_MINUS_SRC_ALPHA`
+    /// blending.
+    pub fn premultiply(&mut self) {
+        for pixel in self.pixels.chunks_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+            }
+        }
+    }
+}
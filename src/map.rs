@@ -1,32 +1,255 @@
 use rect::Rect;
+use std::collections::HashMap;
+use std::path::Path;
+use tile::{Tile, TileDef};
+use tiled;
+
+/// How far `Map::move_item` moves an item at once, in tiles. Has to be less
+/// than a tile so it can't jump over one.
+const MAX_MOVE_STEP: f64 = 0.5;
+
+/// How far `Map::move_item` moves an item at most, in tiles. Longer moves are
+/// cut short so absurd speeds or frame times can't make it take forever.
+const MAX_MOVE_DISTANCE: f64 = 256.0;
 
 pub struct Map {
-    pub blocks: Vec<Vec<u8>>,
+    pub blocks: Vec<Vec<Tile>>,
+    pub spawn: (f64, f64),
+    pub triggers: Vec<Trigger>,
+    pub metadata: HashMap<String, String>,
+}
+
+/// A named area of the map, e.g. a checkpoint or the goal.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub kind: String,
+    pub rect: Rect,
 }
 
 impl Map {
-    pub fn new(blocks: Vec<Vec<u8>>) -> Map {
-        return Map { blocks: blocks };
+    pub fn new(blocks: Vec<Vec<Tile>>) -> Map {
+        return Map {
+            blocks: blocks,
+            spawn: (1.0, 0.0),
+            triggers: Vec::new(),
+            metadata: HashMap::new(),
+        };
+    }
+
+    /// A map from rows of `.` (empty) and `X` (solid) tiles, for tests.
+    #[cfg(test)]
+    pub fn from_rows(rows: &[&str]) -> Map {
+        let src = format!(
+            "width {}\nheight {}\nlegend . empty\nlegend X solid\nmap\n{}",
+            rows[0].len(),
+            rows.len(),
+            rows.join("\n")
+        );
+        Map::parse(&src).unwrap()
+    }
+
+    /// Loads a level file, or a Tiled map if the extension is `.tmx` or `.json`.
+    pub fn load(path: &Path) -> Result<Map, String> {
+        let src = std::fs::read_to_string(path)
+            .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+
+        let map = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tmx") => tiled::parse_tmx(&src),
+            Some("json") | Some("tmj") => tiled::parse_json(&src),
+            _ => Map::parse(&src),
+        };
+
+        map.and_then(|map| map.validate().map(|_| map))
+            .map_err(|err| format!("{} in {}", err, path.display()))
+    }
+
+    /// Parses a level file. Levels are plain text, `#` at the start of a line
+    /// starts a comment:
+    ///
+    /// ```text
+    /// meta name Default      # any number of key/value pairs
+    /// width 16
+    /// height 9
+    /// spawn 1 0              # player position in tiles
+    /// legend . empty         # which tile a character in the map stands for,
+    /// legend X solid         # by name or id
+    /// map                    # followed by `height` lines of `width` characters
+    /// X..............X
+    /// ```
+    pub fn parse(src: &str) -> Result<Map, String> {
+        let mut width = None;
+        let mut height = None;
+        let mut legend = HashMap::new();
+        let mut map = Map::new(Vec::new());
+        let mut lines = src.lines().enumerate();
+
+        while let Some((i, line)) = lines.next() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = || format!("Invalid line {}: {}", i + 1, line);
+
+            match words.as_slice() {
+                [] => (),
+                [comment, ..] if comment.starts_with('#') => (),
+                ["meta", key, value @ ..] => {
+                    map.metadata.insert(key.to_string(), value.join(" "));
+                }
+                ["width", w] => width = Some(w.parse::<usize>().map_err(|_| error())?),
+                ["height", h] => height = Some(h.parse::<usize>().map_err(|_| error())?),
+                ["spawn", x, y] => {
+                    map.spawn = (
+                        x.parse().map_err(|_| error())?,
+                        y.parse().map_err(|_| error())?,
+                    )
+                }
+                ["legend", c, name] if c.chars().count() == 1 => {
+                    let tile = Tile::from_name(name)
+                        .ok_or_else(|| format!("Unknown tile \"{}\" on line {}", name, i + 1))?;
+                    legend.insert(c.chars().next().unwrap(), tile);
+                }
+                ["map"] => {
+                    let width = width.ok_or("width is missing")?;
+                    let height = height.ok_or("height is missing")?;
+
+                    for _ in 0..height {
+                        let (i, line) = lines.next().ok_or("map has less rows than height")?;
+                        let mut row = Vec::with_capacity(width);
+
+                        for c in line.chars() {
+                            match legend.get(&c) {
+                                Some(tile) => row.push(*tile),
+                                None => Err(format!("Unknown tile '{}' on line {}", c, i + 1))?,
+                            }
+                        }
+
+                        if row.len() != width {
+                            Err(format!("Line {} isn't {} tiles wide", i + 1, width))?
+                        }
+
+                        map.blocks.push(row);
+                    }
+                }
+                _ => Err(error())?,
+            }
+        }
+
+        if map.blocks.is_empty() {
+            Err("map is missing")?
+        }
+
+        Ok(map)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (x, y) = self.spawn;
+        if x < 0.0 || y < 0.0 || x >= self.width() as f64 || y >= self.height() as f64 {
+            Err(format!("spawn {} {} is outside of the map", x, y))?
+        }
+
+        Ok(())
+    }
+
+    /// All triggers that overlap `rect`.
+    pub fn triggers_at<'a>(&'a self, rect: &'a Rect) -> impl Iterator<Item = &'a Trigger> + 'a {
+        self.triggers
+            .iter()
+            .filter(move |trigger| trigger.rect.intersects(rect))
+    }
+
+    pub fn width(&self) -> usize {
+        self.blocks.first().map_or(0, |line| line.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.blocks.len()
     }
 
-    pub fn move_item(&self, rect: &mut Rect, dx: f64, dy: f64, time_passed: f64) -> Collision {
+    /// Whether any tile overlapping `rect` matches `f`.
+    pub fn any_tile<F>(&self, rect: &Rect, f: F) -> bool
+    where
+        F: Fn(&TileDef) -> bool,
+    {
+        for y in rect.y.floor() as i64..rect.bottom().ceil() as i64 {
+            for x in rect.x.floor() as i64..rect.right().ceil() as i64 {
+                if f(self.get_i(x, y).def()) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Moves `rect` by `dx`, `dy` tiles per second and stops it at solid
+    /// tiles. Long moves are split into steps of at most `MAX_MOVE_STEP` so
+    /// no tile along the way is skipped. Moves are at most `MAX_MOVE_DISTANCE`
+    /// long.
+    pub fn move_item(
+        &self,
+        rect: &mut Rect,
+        dx: f64,
+        dy: f64,
+        time_passed: f64,
+        options: MoveOptions,
+    ) -> Collision {
+        let distance = dx.abs().max(dy.abs()) * time_passed;
+        let time_passed = time_passed * (MAX_MOVE_DISTANCE / distance).min(1.0);
+        let distance = distance.min(MAX_MOVE_DISTANCE);
+
+        let steps = (distance / MAX_MOVE_STEP).ceil().max(1.0) as u32;
+        let step_time = time_passed / steps as f64;
+
+        let mut collision = Collision::default();
+        for _ in 0..steps {
+            let step = self.move_step(rect, dx, dy, step_time, options);
+
+            // walls and ceilings hit on the way count, the floor only if the
+            // item is still on it at the end
+            collision = Collision {
+                left: collision.left || step.left,
+                right: collision.right || step.right,
+                top: collision.top || step.top,
+                ..step
+            };
+        }
+
+        collision
+    }
+
+    fn move_step(
+        &self,
+        rect: &mut Rect,
+        dx: f64,
+        dy: f64,
+        time_passed: f64,
+        options: MoveOptions,
+    ) -> Collision {
         let mut collision = Collision::default();
 
         // y position
         if dy != 0.0 {
+            let prev_bottom = rect.bottom();
             rect.y += dy * time_passed;
 
             if dy > 0.0 {
+                let y = rect.bottom() as i64;
+
+                // one-way tiles only count if the item was above them before
+                let lands_on_one_way = !options.drop_through && prev_bottom <= y as f64;
+
                 for x in rect.x as i64..(rect.right() + 0.9999) as i64 {
-                    if self.get_i(x, rect.bottom() as i64) == 1 {
+                    let tile = self.get_i(x, y);
+                    if tile.is_solid() || (tile.def().one_way && lands_on_one_way) {
                         rect.move_bottom(rect.bottom() as i64 as f64);
                         collision.bottom = true;
+                        collision.floor = tile;
                         break;
                     }
                 }
             } else {
                 for x in rect.x as i64..(rect.right() + 0.999) as i64 {
-                    if self.get_i(x, rect.y.floor() as i64) == 1 {
+                    let tile = self.get_i(x, rect.y.floor() as i64);
+                    if tile.is_solid() || tile.def().slope.is_some() {
                         rect.y = (rect.y.floor() as i64 + 1) as f64;
                         collision.top = true;
                         break;
@@ -35,13 +258,18 @@ impl Map {
             }
         }
 
+        // how far the item can be pushed up onto a slope or the solid tile at
+        // the top of one, anything higher is a wall
+        let max_top = rect.bottom() - dx.abs() * time_passed - 0.01;
+
         // x position
         if dx != 0.0 {
             rect.x += dx * time_passed;
 
             if dx > 0.0 {
                 for y in rect.y as i64..(rect.bottom() + 0.999) as i64 {
-                    if self.get_i(rect.right() as i64, y) == 1 {
+                    let height = self.get_i(rect.right() as i64, y).def().height_at(0.0);
+                    if height > 0.0 && y as f64 + 1.0 - height < max_top {
                         rect.move_right(rect.right() as i64 as f64);
                         collision.right = true;
                         break;
@@ -49,7 +277,8 @@ impl Map {
                 }
             } else {
                 for y in rect.y as i64..(rect.bottom() + 0.999) as i64 {
-                    if self.get_i(rect.x.floor() as i64, y) == 1 {
+                    let height = self.get_i(rect.x.floor() as i64, y).def().height_at(1.0);
+                    if height > 0.0 && y as f64 + 1.0 - height < max_top {
                         rect.x = (rect.x.floor() as i64 + 1) as f64;
                         collision.left = true;
                         break;
@@ -58,18 +287,70 @@ impl Map {
             }
         }
 
+        // slopes, and following the ground down them
+        if dy >= 0.0 {
+            let snap_down = if options.snap_to_ground {
+                dx.abs() * time_passed + 0.01
+            } else {
+                0.0
+            };
+
+            // falling into a slope sinks the item into it as well
+            let min_y = max_top - dy * time_passed;
+
+            if let Some((surface, tile)) = self.floor_at(rect, min_y, rect.bottom() + snap_down) {
+                rect.move_bottom(surface);
+                collision.bottom = true;
+                collision.floor = tile;
+                collision.slope = tile.def().slope_angle();
+            }
+        }
+
         collision
     }
 
-    fn get_i(&self, x: i64, y: i64) -> u8 {
+    /// The highest floor under `rect` with its top between `min_y` and
+    /// `max_y`, and the tile it belongs to.
+    fn floor_at(&self, rect: &Rect, min_y: f64, max_y: f64) -> Option<(f64, Tile)> {
+        let mut floor: Option<(f64, Tile)> = None;
+
+        // both corners and the middle, the corners stop the item from sinking
+        // into solid tiles at the top of a slope
+        for &x in &[
+            rect.x + 0.0001,
+            rect.x + rect.width / 2.0,
+            rect.right() - 0.0001,
+        ] {
+            let column = x.floor();
+
+            for y in min_y.floor() as i64..=max_y.floor() as i64 {
+                let tile = self.get_i(column as i64, y);
+                let height = tile.def().height_at(x - column);
+                let surface = y as f64 + 1.0 - height;
+
+                if height > 0.0
+                    && surface >= min_y
+                    && surface <= max_y
+                    && surface < floor.map_or(f64::INFINITY, |(top, _)| top)
+                {
+                    floor = Some((surface, tile));
+                }
+            }
+        }
+
+        floor
+    }
+
+    /// The tile at `x`, `y`. Everything outside of the map is solid.
+    pub fn get_i(&self, x: i64, y: i64) -> Tile {
         //println!("getting {} {}", x, y);
         if x < 0 || y < 0 || y >= self.blocks.len() as i64 {
-            1u8
+            Tile::Solid
         } else {
             let line = &self.blocks[y as usize];
 
             if x >= line.len() as i64 {
-                1u8
+                Tile::Solid
             } else {
                 line[x as usize]
             }
@@ -77,12 +358,26 @@ impl Map {
     }
 }
 
-#[derive(Default, Debug)]
+/// Extra rules for `Map::move_item`.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct MoveOptions {
+    /// Fall through one-way tiles instead of landing on them.
+    pub drop_through: bool,
+    /// Keep the item on the ground when it walks down a slope instead of
+    /// letting it fall off.
+    pub snap_to_ground: bool,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 pub struct Collision {
     pub left: bool,
     pub right: bool,
     pub top: bool,
     pub bottom: bool,
+    /// The tile the item landed on, `Tile::Empty` if it didn't.
+    pub floor: Tile,
+    /// The angle of the floor in radians, positive if it goes up to the right.
+    pub slope: f64,
 }
 
 impl Collision {
@@ -90,3 +385,91 @@ impl Collision {
         return self.bottom;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::{MAX_FRAME_TIME, TIME_STEP};
+
+    /// Moves `rect` for a second in steps of `time_step`, or once if the step
+    /// is longer.
+    fn move_for_a_second(
+        map: &Map,
+        rect: &mut Rect,
+        dx: f64,
+        dy: f64,
+        time_step: f64,
+    ) -> Collision {
+        let mut collision = Collision::default();
+        for _ in 0..(1.0 / time_step).ceil() as usize {
+            let step = map.move_item(rect, dx, dy, time_step, MoveOptions::default());
+            collision.left |= step.left;
+            collision.right |= step.right;
+            collision.bottom |= step.bottom;
+        }
+        collision
+    }
+
+    #[test]
+    fn walls_stop_fast_items() {
+        let map = Map::from_rows(&["............", ".....X......", "............"]);
+
+        for &time_step in &[TIME_STEP, MAX_FRAME_TIME] {
+            let mut rect = Rect::new(3.0, 1.2, 0.9, 0.6);
+            let collision = move_for_a_second(&map, &mut rect, 10.0, 0.0, time_step);
+            assert!(collision.right);
+            assert_eq!(rect.right(), 5.0);
+
+            let mut rect = Rect::new(7.0, 1.2, 0.9, 0.6);
+            let collision = move_for_a_second(&map, &mut rect, -10.0, 0.0, time_step);
+            assert!(collision.left);
+            assert_eq!(rect.x, 6.0);
+        }
+    }
+
+    #[test]
+    fn default_map_walls_stop_fast_items() {
+        let map = Map::load(Path::new("content/levels/default.level")).unwrap();
+
+        // the 1-tile wall in the middle and the outer walls, at the
+        // fastest the player runs
+        for &time_step in &[TIME_STEP, MAX_FRAME_TIME] {
+            for &(x, dx, stop) in &[(3.0, 10.0, 7.0), (11.0, -10.0, 8.0)] {
+                let mut rect = Rect::new(x, 4.2, 0.9, 0.6);
+                move_for_a_second(&map, &mut rect, dx, 0.0, time_step);
+                let edge = if dx > 0.0 { rect.right() } else { rect.x };
+                assert_eq!(edge, stop);
+            }
+
+            for &(x, dx, stop) in &[(3.0, -10.0, 1.0), (12.0, 10.0, 15.0)] {
+                let mut rect = Rect::new(x, 7.2, 0.9, 0.6);
+                move_for_a_second(&map, &mut rect, dx, 0.0, time_step);
+                let edge = if dx > 0.0 { rect.right() } else { rect.x };
+                assert_eq!(edge, stop);
+            }
+        }
+    }
+
+    #[test]
+    fn absurd_moves_finish() {
+        let map = Map::from_rows(&["....", "....", "XXXX"]);
+
+        let mut rect = Rect::new(1.0, 0.0, 0.9, 0.6);
+        let collision = map.move_item(&mut rect, 1e12, 1e12, 1e3, MoveOptions::default());
+        assert!(collision.right && collision.bottom);
+        assert_eq!((rect.right(), rect.bottom()), (4.0, 2.0));
+    }
+
+    #[test]
+    fn floors_stop_falling_items() {
+        let map = Map::from_rows(&["....", "....", "....", "XXXX", "....", "...."]);
+
+        // the fastest the player falls
+        for &time_step in &[TIME_STEP, MAX_FRAME_TIME] {
+            let mut rect = Rect::new(1.5, 1.0, 0.9, 0.6);
+            let collision = move_for_a_second(&map, &mut rect, 0.0, 13.0, time_step);
+            assert!(collision.bottom);
+            assert_eq!(rect.bottom(), 3.0);
+        }
+    }
+}
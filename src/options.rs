@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+pub const DEFAULT_LEVEL: &str = "levels/default.level";
+
+/// Command line options.
+pub struct Options {
+    /// The level to play, relative to the content directory.
+    pub level: String,
+    /// The content directory, see `Roots::find`.
+    pub content: Option<PathBuf>,
+    /// Directories with files that replace the ones in the content directory.
+    pub mods: Vec<PathBuf>,
+    /// Runs without a window, needs either `script` or `replay`.
+    pub headless: bool,
+    /// Drives the player from a headless script (see `headless::Script`).
+    pub script: Option<PathBuf>,
+    /// Plays back a recorded replay instead of reading the keyboard.
+    pub replay: Option<PathBuf>,
+    /// Records the input of the run and saves it here on exit.
+    pub record: Option<PathBuf>,
+    /// Fullscreen at the desktop resolution.
+    pub fullscreen: bool,
+    /// A window without decorations.
+    pub borderless: bool,
+    /// Renders at the full resolution of high-DPI displays.
+    pub highdpi: bool,
+    /// What to do when the window doesn't have the aspect ratio of the view.
+    pub view: View,
+}
+
+/// How the 16x9 tile view is fit into a window with a different aspect ratio.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum View {
+    /// Adds black bars at the sides or top and bottom.
+    Letterbox,
+    /// Shows more of the map.
+    Extend,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            level: DEFAULT_LEVEL.to_owned(),
+            content: None,
+            mods: Vec::new(),
+            headless: false,
+            script: None,
+            replay: None,
+            record: None,
+            fullscreen: false,
+            borderless: false,
+            highdpi: false,
+            view: View::Letterbox,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse<I>(args: I) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--level" => options.level = value(&mut args, &arg)?,
+                "--content" => options.content = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--mod" => options.mods.push(PathBuf::from(value(&mut args, &arg)?)),
+                "--headless" => options.headless = true,
+                "--script" => options.script = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--replay" => options.replay = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--record" => options.record = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--fullscreen" => options.fullscreen = true,
+                "--borderless" => options.borderless = true,
+                "--highdpi" => options.highdpi = true,
+                "--view" => {
+                    options.view = match value(&mut args, &arg)?.as_str() {
+                        "letterbox" => View::Letterbox,
+                        "extend" => View::Extend,
+                        view => Err(format!("Unknown view: {}, use letterbox or extend", view))?,
+                    }
+                }
+                _ => Err(format!("Unknown argument: {}", arg))?,
+            }
+        }
+
+        if options.script.is_some() && options.replay.is_some() {
+            Err("--script and --replay can't be used together")?
+        }
+        if options.script.is_some() && !options.headless {
+            Err("--script needs --headless")?
+        }
+        if options.record.is_some() && options.headless {
+            Err("--record can't be used with --headless")?
+        }
+        if options.fullscreen && options.borderless {
+            Err("--fullscreen and --borderless can't be used together")?
+        }
+
+        Ok(options)
+    }
+}
+
+fn value<I>(args: &mut I, name: &str) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", name))
+}
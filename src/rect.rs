@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
 pub struct Rect {
     pub x: f64,
     pub y: f64,
@@ -31,4 +31,22 @@ impl Rect {
     pub fn move_bottom(&mut self, bottom: f64) {
         self.y = bottom - self.height;
     }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    /// Linearly interpolates between `self` (t = 0) and `other` (t = 1).
+    #[cfg(feature = "window")]
+    pub fn lerp(&self, other: &Rect, t: f64) -> Rect {
+        Rect {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            width: self.width + (other.width - self.width) * t,
+            height: self.height + (other.height - self.height) * t,
+        }
+    }
 }
@@ -0,0 +1,240 @@
+use game::{Input, MAX_FRAME_TIME};
+use options::DEFAULT_LEVEL;
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "gaem-replay 1";
+
+/// The most steps a replay can have, a day at 120 Hz, so a broken replay
+/// file can't use up all memory.
+const MAX_STEPS: usize = 120 * 60 * 60 * 24;
+
+/// The input of every simulation step of a run, together with everything
+/// needed to start the run again.
+///
+/// Replays are saved as text. Inputs are run-length encoded, one line per run
+/// of identical steps: the step count followed by the keys, `L` (left), `R`
+/// (right), `J` (jump held), `P` (jump pressed) and `D` (down), `.` for
+/// released keys. Older replays without the `D` column are still accepted.
+///
+/// ```text
+/// gaem-replay 1
+/// dt 0.008333333333333333
+/// level levels/default.level
+/// spawn 1 0
+/// 30 .....
+/// 1 ..JP.
+/// 12 .RJ..
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub time_step: f64,
+    /// The level, relative to the content directory.
+    pub level: String,
+    pub spawn: (f64, f64),
+    pub inputs: Vec<Input>,
+}
+
+impl Replay {
+    pub fn new(time_step: f64, level: &str, spawn: (f64, f64)) -> Replay {
+        Replay {
+            time_step,
+            level: level.to_owned(),
+            spawn,
+            inputs: Vec::new(),
+        }
+    }
+
+    #[cfg(any(feature = "window", test))]
+    pub fn push(&mut self, input: Input) {
+        self.inputs.push(input);
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let src = fs::read_to_string(path)
+            .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+
+        Replay::parse(&src).map_err(|err| format!("{} in {}", err, path.display()))
+    }
+
+    #[cfg(feature = "window")]
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|err| format!("{} when saving {}", err, path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<Replay, String> {
+        let mut lines = src.lines().enumerate();
+
+        match lines.next() {
+            Some((_, HEADER)) => (),
+            _ => Err("Not a replay file")?,
+        }
+
+        let mut replay = Replay::new(0.0, DEFAULT_LEVEL, (0.0, 0.0));
+
+        for (i, line) in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = || format!("Invalid replay line {}: {}", i + 1, line);
+
+            match words.as_slice() {
+                [] => (),
+                ["dt", dt] => replay.time_step = dt.parse().map_err(|_| error())?,
+                ["level", level] => replay.level = level.to_string(),
+                ["spawn", x, y] => {
+                    replay.spawn = (
+                        x.parse().map_err(|_| error())?,
+                        y.parse().map_err(|_| error())?,
+                    )
+                }
+                [count, keys] => {
+                    let count: usize = count.parse().map_err(|_| error())?;
+                    let input = parse_keys(keys).ok_or_else(error)?;
+                    if count > MAX_STEPS - replay.inputs.len() {
+                        Err(format!("Replay is longer than {} steps", MAX_STEPS))?
+                    }
+
+                    let len = replay.inputs.len();
+                    replay.inputs.resize(len + count, input);
+                }
+                _ => Err(error())?,
+            }
+        }
+
+        // also rejects NaN
+        if !(replay.time_step > 0.0 && replay.time_step <= MAX_FRAME_TIME) {
+            Err(format!(
+                "Replay is missing a time step between 0 and {}",
+                MAX_FRAME_TIME
+            ))?
+        }
+
+        Ok(replay)
+    }
+
+    #[cfg(any(feature = "window", test))]
+    pub fn to_text(&self) -> String {
+        // f64's Display is the shortest representation that parses back to
+        // the same value, so the time step and spawn survive the round trip.
+        let mut text = format!(
+            "{}\ndt {}\nlevel {}\nspawn {} {}\n",
+            HEADER, self.time_step, self.level, self.spawn.0, self.spawn.1
+        );
+
+        let mut i = 0;
+        while i < self.inputs.len() {
+            let input = self.inputs[i];
+            let count = self.inputs[i..]
+                .iter()
+                .take_while(|other| **other == input)
+                .count();
+
+            text.push_str(&format!("{} {}\n", count, format_keys(&input)));
+            i += count;
+        }
+
+        text
+    }
+}
+
+#[cfg(any(feature = "window", test))]
+fn format_keys(input: &Input) -> String {
+    let key = |down, c| if down { c } else { '.' };
+
+    [
+        key(input.is_left_down, 'L'),
+        key(input.is_right_down, 'R'),
+        key(input.is_jump_down, 'J'),
+        key(input.is_jump_press, 'P'),
+        key(input.is_down_down, 'D'),
+    ]
+    .iter()
+    .collect()
+}
+
+fn parse_keys(keys: &str) -> Option<Input> {
+    let keys: Vec<char> = keys.chars().collect();
+    if keys.len() != 4 && keys.len() != 5 {
+        return None;
+    }
+
+    let key = |i: usize, c| match keys.get(i) {
+        None | Some('.') => Some(false),
+        Some(k) if *k == c => Some(true),
+        _ => None,
+    };
+
+    Some(Input {
+        is_left_down: key(0, 'L')?,
+        is_right_down: key(1, 'R')?,
+        is_jump_down: key(2, 'J')?,
+        is_jump_press: key(3, 'P')?,
+        is_down_down: key(4, 'D')?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::{Player, TIME_STEP};
+    use headless::{play, Script};
+    use map::Map;
+
+    #[test]
+    fn rejects_invalid_time_steps() {
+        for dt in &["0", "-1", "NaN", "inf", "1e9"] {
+            let src = format!("{}\ndt {}\n1 .....\n", HEADER, dt);
+            assert!(Replay::parse(&src).is_err(), "replay accepted dt {}", dt);
+
+            let src = format!("dt {}\nsteps 1\n", dt);
+            assert!(Script::parse(&src).is_err(), "script accepted dt {}", dt);
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_steps() {
+        let src = format!("{}\ndt 0.01\n999999999999 .....\n", HEADER);
+        assert!(Replay::parse(&src).is_err());
+
+        let src = format!("{}\ndt 0.01\n{} .....\n1 ..JP.\n", HEADER, MAX_STEPS);
+        assert!(Replay::parse(&src).is_err());
+    }
+
+    #[test]
+    fn playback_reproduces_the_run() {
+        let map = Map::load(Path::new("content/levels/default.level")).unwrap();
+        let mut replay = Replay::new(TIME_STEP, "levels/default.level", map.spawn);
+        let mut player = Player::new(map.spawn.0, map.spawn.1);
+
+        // run into the wall in the middle and jump at it a few times
+        for step in 0..600 {
+            let input = Input {
+                is_right_down: step > 60 && step < 400,
+                is_jump_down: step % 100 > 70,
+                is_jump_press: step % 100 == 71,
+                ..Input::default()
+            };
+            replay.push(input);
+            player.step(&input, replay.time_step, &map);
+        }
+
+        let loaded = Replay::parse(&replay.to_text()).unwrap();
+        assert_eq!(loaded, replay);
+
+        let played = play(&loaded, &map);
+        assert_eq!(played.rect, player.rect);
+        assert_eq!(played.collision, player.collision);
+    }
+
+    #[test]
+    fn reads_replays_without_the_down_column() {
+        let old = format!("{}\ndt 0.01\n2 .R..\n1 L.JP\n", HEADER);
+        let new = format!("{}\ndt 0.01\n2 .R...\n1 L.JP.\n", HEADER);
+
+        let replay = Replay::parse(&old).unwrap();
+        assert_eq!(replay, Replay::parse(&new).unwrap());
+        assert_eq!(replay.inputs.len(), 3);
+        assert!(replay.inputs[2].is_jump_press);
+        assert!(!replay.inputs[2].is_down_down);
+    }
+}
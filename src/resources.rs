@@ -1,96 +1,137 @@
 extern crate colored;
 extern crate notify;
-extern crate png;
 
 use self::colored::Colorize;
-use self::notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use self::notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use assets::{Asset, Assets, Handle, Store};
+use camera::Camera;
 use gl;
 use gl::types::*;
+use glsl;
+use glsl::Source;
+use image;
+use image::{Filter, TextureOptions, Wrap};
+use map::Map;
 use rect::Rect;
+use roots::{canonical, Roots};
+use sdl2::audio::{AudioCVT, AudioFormat, AudioQueue, AudioSpecDesired, AudioSpecWAV};
+use sdl2::Sdl;
+use sprite::Sheet;
+use std::any::TypeId;
 use std::collections::HashMap;
-use std::fs::File;
-use std::mem::transmute;
-use std::path::PathBuf;
+use std::ffi::CString;
+use std::mem::{size_of, transmute};
+use std::path::{Path, PathBuf};
+use std::ptr;
 use std::sync::mpsc::{channel, Receiver};
 use std::time::Duration;
+use tileset::Tileset;
 
-pub fn init_drawing() {
-    unsafe {
-        gl::Enable(gl::TEXTURE_2D);
-    }
-}
-
+/// Loads assets from the content directory and keeps them loaded while they
+/// are used. Assets are shared by everything that loads the same key, and are
+/// reloaded when their files change.
 pub struct Content {
-    base_path: PathBuf,
+    roots: Roots,
     resource_versions: HashMap<PathBuf, u64>,
     #[allow(dead_code)]
     watcher: Option<RecommendedWatcher>,
     receiver: Receiver<notify::DebouncedEvent>,
+    /// The `Assets` of each type.
+    stores: HashMap<TypeId, Box<dyn Store>>,
 }
 
 impl Content {
-    pub fn new() -> Content {
-        // #[cfg(debug_assertions)]
-        // let s = std::fs::read_to_string("./contentpath").unwrap();
-        // #[cfg(not(debug_assertions))]
-        let s = String::from("./content");
-
-        println!("Content path: {}", s);
-
-        let mut buf = PathBuf::new();
-        buf.push(s);
+    pub fn new(roots: Roots) -> Content {
+        for dir in roots.dirs() {
+            println!("Content path: {}", dir.display());
+        }
 
         let (sender, receiver) = channel();
 
-        let mut w = RecommendedWatcher::new(sender, Duration::from_millis(200)).ok();
-        if w.is_some() {
-            w.as_mut()
-                .unwrap()
-                .watch("./content", RecursiveMode::Recursive)
-                .expect("xD");
+        // hot reload is only for development, the game works without it
+        let mut watcher = RecommendedWatcher::new(sender, Duration::from_millis(200)).ok();
+        if let Some(ref mut watcher) = watcher {
+            for dir in roots.dirs() {
+                if let Err(err) = watcher.watch(dir, RecursiveMode::Recursive) {
+                    println!("Error watching {}: {}", dir.display(), err);
+                }
+            }
         }
 
         Content {
-            base_path: buf,
+            roots,
             resource_versions: HashMap::default(),
-            watcher: w,
-            receiver: receiver,
+            watcher,
+            receiver,
+            stores: HashMap::new(),
         }
     }
 
+    /// Reloads the assets whose files changed and unloads the unused ones.
     pub fn update(&mut self) {
-        // for (k, v) in &self.shader_versions {
-        //     println!("{:?} , {}", k, v)
-        // }
-
         for event in self.receiver.try_iter() {
-            // println!("changed: {:?}", event.kind);
             match event {
-                notify::DebouncedEvent::Write(path) => {
-                    let new_path = path.canonicalize().unwrap();
-                    match self.resource_versions.get_mut(&new_path) {
-                        Some(val) => *val = *val + 1,
-                        _ => println!("Didn't find: {:?}", &new_path),
+                // editors that save by replacing the file create or rename it
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path) => changed(&mut self.resource_versions, &path),
+                DebouncedEvent::Rename(from, to) => {
+                    changed(&mut self.resource_versions, &from);
+                    changed(&mut self.resource_versions, &to);
+                }
+                // events were lost, anything could have changed
+                DebouncedEvent::Rescan => {
+                    for version in self.resource_versions.values_mut() {
+                        *version += 1;
                     }
                 }
+                DebouncedEvent::Error(err, path) => {
+                    println!("Error watching {:?}: {}", path, err.to_string().red())
+                }
                 _ => (),
             }
         }
-    }
 
-    fn should_update_resource(&mut self, path: &PathBuf, current_version: &mut u64) -> bool {
-        match self.resource_versions.get(path) {
-            Some(new_version) => {
-                if *new_version != *current_version {
-                    *current_version = *new_version;
-                    true
-                } else {
-                    false
-                }
-            }
-            _ => false,
+        for store in self.stores.values_mut() {
+            store.update(&self.roots, &mut self.resource_versions);
         }
     }
+
+    /// Loads an asset, or gives another handle to it if it's loaded already.
+    pub fn load<T: Asset>(&mut self, key: T::Key) -> Result<Handle<T>, String> {
+        let store = self
+            .stores
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Assets::<T>::default()));
+        let assets = store.as_any_mut().downcast_mut::<Assets<T>>().unwrap();
+
+        assets.load(key, &self.roots, &mut self.resource_versions)
+    }
+
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> &T {
+        self.stores[&TypeId::of::<T>()]
+            .as_any()
+            .downcast_ref::<Assets<T>>()
+            .unwrap()
+            .get(handle)
+    }
+
+    pub fn get_mut<T: Asset>(&mut self, handle: &Handle<T>) -> &mut T {
+        self.stores
+            .get_mut(&TypeId::of::<T>())
+            .unwrap()
+            .as_any_mut()
+            .downcast_mut::<Assets<T>>()
+            .unwrap()
+            .get_mut(handle)
+    }
+}
+
+/// Bumps the version of `path` if an asset uses it.
+fn changed(versions: &mut HashMap<PathBuf, u64>, path: &Path) {
+    if let Some(version) = versions.get_mut(&canonical(path)) {
+        *version += 1;
+    }
 }
 
 #[derive(Default)]
@@ -100,42 +141,160 @@ pub struct Shader {
     native_frag: Option<u32>,
     native_vert: Option<u32>,
     native_program: Option<u32>,
-    frag_version: u64, // for auto-reload
-    vert_version: u64, // for auto-reload
+    /// Every file the shader was made of the last time it was loaded.
+    files: Vec<PathBuf>,
+    /// Where `#include`d files are looked up.
+    include_dirs: Vec<PathBuf>,
+    /// The defines from the key, and `DEBUG` in debug builds.
+    defines: Vec<(String, String)>,
+    error: Option<String>,
+    /// Uploaded every time the shader is selected.
+    uniforms: HashMap<&'static str, Uniform>,
+    /// Uniform locations by name, -1 for uniforms the shader doesn't use.
+    locations: HashMap<&'static str, GLint>,
+}
+
+/// A value for a shader uniform, see `Shader::set`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Uniform {
+    Float(f32),
+    Vec2(f32, f32),
+    Vec3(f32, f32, f32),
+    Vec4(f32, f32, f32, f32),
+    Mat4([f32; 16]),
+    /// The texture unit a `sampler2D` reads from.
+    Sampler(GLint),
+}
+
+/// Where the renderer puts each vertex attribute, bound by name for shaders
+/// that don't set `layout(location = ...)` themselves.
+const ATTRIBUTES: &[(GLuint, &str)] = &[
+    (ATTR_POS, "vertPos"),
+    (ATTR_TEX_COORD, "texCoord"),
+    (ATTR_COLOR, "vertColor"),
+];
+const ATTR_POS: GLuint = 0;
+const ATTR_TEX_COORD: GLuint = 1;
+const ATTR_COLOR: GLuint = 2;
+
+/// Draws the vertex colors, see `Shader::plain`.
+const PLAIN_VERT: &str = "#version 330 core
+
+in vec3 vertPos;
+in vec4 vertColor;
+
+uniform mat4 viewMatrix, projMatrix;
+
+out vec4 color;
+
+void main() {
+  gl_Position = projMatrix * viewMatrix * vec4(vertPos, 1.0);
+  color = vertColor;
 }
+";
+const PLAIN_FRAG: &str = "#version 330 core
 
-fn register_file(content: &mut Content, path: &str) -> PathBuf {
-    let mut buf = PathBuf::new();
-    buf.push(&content.base_path);
-    buf.push(path);
-    buf = buf.canonicalize().unwrap();
+in vec4 color;
 
-    &content.resource_versions.insert(buf.clone(), 0);
+out vec4 frag_color;
 
-    buf
+void main() {
+  frag_color = color;
 }
+";
+
+/// The texture unit `Renderer::texture` binds to, available to shaders as
+/// `uniform sampler2D texture1`.
+const TEXTURE_UNIT: GLint = 0;
 
 impl Shader {
-    /// Creates a fragment shader.
-    pub fn frag(content: &mut Content, path: &str) -> Shader {
-        // println!("Registering: {:?}", path);
+    /// Loads a shader from a vertex and fragment shader file. Vertex shaders
+    /// get the `vertPos`, `texCoord` and `vertColor` attributes and the
+    /// `viewMatrix` and `projMatrix` uniforms, see `shaders/2d.vert`.
+    ///
+    /// A shader that doesn't compile is still loaded, see `error`.
+    pub fn new(
+        content: &mut Content,
+        vert_path: &str,
+        frag_path: &str,
+    ) -> Result<Handle<Shader>, String> {
+        Shader::with_defines(content, vert_path, frag_path, &[])
+    }
 
-        Shader {
-            frag_path: register_file(content, path),
-            vert_path: register_file(content, "shaders/2d.vert"),
-            ..Default::default()
-        }
+    /// Loads a shader with `#define name value` added to both the vertex and
+    /// fragment shader for each of `defines`. The same files with other
+    /// defines are another shader.
+    pub fn with_defines(
+        content: &mut Content,
+        vert_path: &str,
+        frag_path: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Handle<Shader>, String> {
+        let defines = defines
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+
+        content.load((vert_path.to_owned(), frag_path.to_owned(), defines))
     }
 
-    pub fn reset() {
-        unsafe {
-            gl::UseProgram(0);
+    /// Sets a uniform. It's uploaded when the shader is selected next, so
+    /// changing it between two draws needs another `Renderer::shader`.
+    /// Uniforms the shader doesn't have are ignored.
+    pub fn set(&mut self, name: &'static str, value: Uniform) {
+        self.uniforms.insert(name, value);
+    }
+
+    fn upload_uniforms(&mut self) {
+        let program = match self.native_program {
+            Some(program) => program,
+            None => return,
+        };
+
+        for (&name, value) in &self.uniforms {
+            let location = *self.locations.entry(name).or_insert_with(|| unsafe {
+                let name = CString::new(name).unwrap();
+                gl::GetUniformLocation(program, name.as_ptr())
+            });
+            if location == -1 {
+                continue;
+            }
+
+            unsafe {
+                match *value {
+                    Uniform::Float(x) => gl::Uniform1f(location, x),
+                    Uniform::Vec2(x, y) => gl::Uniform2f(location, x, y),
+                    Uniform::Vec3(x, y, z) => gl::Uniform3f(location, x, y, z),
+                    Uniform::Vec4(x, y, z, w) => gl::Uniform4f(location, x, y, z, w),
+                    Uniform::Mat4(m) => gl::UniformMatrix4fv(location, 1, gl::FALSE, m.as_ptr()),
+                    Uniform::Sampler(unit) => gl::Uniform1i(location, unit),
+                }
+            }
         }
     }
 
-    unsafe fn load_file(path: &PathBuf, type_: GLenum) -> Result<u32, String> {
-        let bytes = std::fs::read(path)
-            .map_err(|err| format!("{} when loading {}", err, path.to_str().unwrap()))?;
+    /// Loads a fragment shader, using the default vertex shader.
+    pub fn frag(content: &mut Content, path: &str) -> Result<Handle<Shader>, String> {
+        Shader::new(content, "shaders/2d.vert", path)
+    }
+
+    /// A shader that draws the vertex colors. It's built into the game, so it
+    /// works no matter what happens to the content.
+    pub fn plain() -> Result<Shader, String> {
+        let (vert, frag, program) = Shader::link(
+            &Source::new("plain vertex shader", PLAIN_VERT),
+            &Source::new("plain fragment shader", PLAIN_FRAG),
+        )?;
+
+        let mut shader = Shader::default();
+        shader.native_program = Some(program);
+        shader.native_vert = Some(vert);
+        shader.native_frag = Some(frag);
+        Ok(shader)
+    }
+
+    unsafe fn compile(source: &Source, type_: GLenum) -> u32 {
+        let bytes = source.text.as_bytes();
 
         let shader = gl::CreateShader(type_);
 
@@ -143,27 +302,38 @@ impl Shader {
         gl::ShaderSource(shader, 1, transmute(&bytes.as_ptr()), transmute(&len));
         gl::CompileShader(shader);
 
-        Ok(shader)
+        shader
     }
 
-    unsafe fn check_shader_status(program: u32) -> Result<(), String> {
-        let buf_len: GLint = 0;
-        gl::GetShaderiv(program, gl::INFO_LOG_LENGTH, transmute(&buf_len));
-
-        if buf_len >= 1 {
-            let mut buf: Vec<u8> = vec![0; buf_len as usize];
-            gl::GetShaderInfoLog(
-                program,
-                buf_len,
-                transmute(0u64),
-                transmute(buf.as_mut_ptr()),
+    /// Fails with the compiler log if the shader didn't compile, prints the
+    /// log as warnings if it did.
+    unsafe fn check_shader_status(shader: u32, source: &Source) -> Result<(), String> {
+        let mut compiled: GLint = 0;
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut compiled);
+
+        let mut buf_len: GLint = 0;
+        gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut buf_len);
+
+        let mut buf: Vec<u8> = vec![0; buf_len.max(1) as usize];
+        gl::GetShaderInfoLog(
+            shader,
+            buf_len,
+            ptr::null_mut(),
+            buf.as_mut_ptr() as *mut GLchar,
+        );
+        let log = source.map_log(String::from_utf8_lossy(&buf).trim_end_matches('\0'));
+        let path = source.files[0].display();
+
+        if compiled == 0 {
+            Err(format!("Error compiling {}:\n{}", path, log))?
+        }
+        if !log.is_empty() {
+            print!(
+                "{}",
+                format!("Warnings compiling {}:\n{}", path, log).yellow()
             );
-
-            match String::from_utf8(buf) {
-                Err(err) => Err(format!("Error parsing utf8: {}", err)),
-                Ok(val) => Err(format!("Error loading shader: {}", val)),
-            }?
         }
+
         Ok(())
     }
 
@@ -172,67 +342,109 @@ impl Shader {
         let mut linked: GLint = 1;
         gl::GetProgramiv(program, gl::LINK_STATUS, transmute(&mut linked));
         if linked == 0 {
-            Err("Error linking shader program")?
+            let mut buf_len: GLint = 0;
+            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut buf_len);
+
+            let mut buf: Vec<u8> = vec![0; buf_len.max(1) as usize];
+            gl::GetProgramInfoLog(
+                program,
+                buf_len,
+                ptr::null_mut(),
+                buf.as_mut_ptr() as *mut GLchar,
+            );
+
+            Err(format!(
+                "Error linking shader program: {}",
+                String::from_utf8_lossy(&buf).trim_end_matches('\0')
+            ))?
         }
 
         Ok(())
     }
 
-    pub fn load(&mut self) -> Result<(), String> {
-        // println!("Loading: {:?}", &self.frag_path);
+    /// Compiles the shader. If that fails the previously compiled program is
+    /// kept and the error is available from `error`.
+    fn compile_program(&mut self) -> Result<(), String> {
+        let result = self.load_program();
+        self.error = result.as_ref().err().cloned();
+        let (vert, frag, program) = result?;
 
         // Delete old shader.
         self.unload();
 
-        // Load new shader.
-        unsafe {
-            let vert = Shader::load_file(&self.vert_path, gl::VERTEX_SHADER)?;
-            let frag = Shader::load_file(&self.frag_path, gl::FRAGMENT_SHADER)?;
-
-            let program = gl::CreateProgram();
-            // gl::AttachShader(program, vert);
-            gl::AttachShader(program, frag);
-            gl::LinkProgram(program);
-            Shader::check_shader_status(frag)?;
-            Shader::check_shader_status(vert)?;
-            Shader::check_program_status(program)?;
-
-            self.native_program = Some(program);
-            self.native_vert = Some(vert);
-            self.native_frag = Some(frag);
-        }
+        self.native_program = Some(program);
+        self.native_vert = Some(vert);
+        self.native_frag = Some(frag);
 
         Ok(())
     }
 
-    pub fn try_load(&mut self) {
-        match self.load() {
-            Err(err) => println!(
-                "Error loading shader: {} ({})",
-                self.frag_path.to_str().unwrap(),
-                err.red()
-            ),
-            _ => (),
+    fn load_program(&mut self) -> Result<(u32, u32, u32), String> {
+        let vert_source = glsl::preprocess(&self.vert_path, &self.include_dirs, &self.defines)?;
+        let frag_source = glsl::preprocess(&self.frag_path, &self.include_dirs, &self.defines)?;
+
+        // includes can change with every load
+        self.files.clear();
+        for file in vert_source.files.iter().chain(&frag_source.files) {
+            if !self.files.contains(file) {
+                self.files.push(file.clone());
+            }
         }
+
+        Shader::link(&vert_source, &frag_source).map_err(|err| {
+            format!(
+                "{}\n({} and {})",
+                err,
+                self.vert_path.display(),
+                self.frag_path.display()
+            )
+        })
     }
 
-    pub fn select(&mut self, content: &mut Content) {
-        if content.should_update_resource(&self.frag_path, &mut self.frag_version) {
-            self.unload();
-            self.try_load();
-        }
+    /// Compiles and links a program, returns the vertex shader, fragment
+    /// shader and program.
+    fn link(vert_source: &Source, frag_source: &Source) -> Result<(u32, u32, u32), String> {
+        unsafe {
+            let vert = Shader::compile(vert_source, gl::VERTEX_SHADER);
+            let frag = Shader::compile(frag_source, gl::FRAGMENT_SHADER);
+            let program = gl::CreateProgram();
 
-        if content.should_update_resource(&self.vert_path, &mut self.vert_version) {
-            self.unload();
-            self.try_load();
+            let result = Shader::check_shader_status(vert, vert_source)
+                .and_then(|_| Shader::check_shader_status(frag, frag_source))
+                .and_then(|_| {
+                    gl::AttachShader(program, vert);
+                    gl::AttachShader(program, frag);
+                    for &(location, name) in ATTRIBUTES {
+                        let name = CString::new(name).unwrap();
+                        gl::BindAttribLocation(program, location, name.as_ptr());
+                    }
+                    gl::LinkProgram(program);
+                    Shader::check_program_status(program)
+                });
+
+            if result.is_err() {
+                gl::DeleteProgram(program);
+                gl::DeleteShader(vert);
+                gl::DeleteShader(frag);
+            }
+            result.map(|_| (vert, frag, program))
         }
+    }
+
+    /// Why the last load failed, `None` if it worked.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
 
+    pub fn select(&mut self) {
         match self.native_program {
             Some(native_program) => unsafe {
                 gl::UseProgram(native_program as GLuint);
             },
             _ => (),
         }
+
+        self.upload_uniforms();
     }
 
     fn unload(&mut self) {
@@ -258,6 +470,58 @@ impl Shader {
         self.native_program = None;
         self.native_vert = None;
         self.native_frag = None;
+        // a reloaded shader can have its uniforms elsewhere
+        self.locations.clear();
+    }
+}
+
+impl Asset for Shader {
+    /// The vertex and fragment shader and the defines set in code.
+    type Key = (String, String, Vec<(String, String)>);
+
+    fn load(key: &Self::Key, roots: &Roots) -> Result<Shader, String> {
+        let mut shader = Shader::default();
+        shader.set("texture1", Uniform::Sampler(TEXTURE_UNIT));
+
+        shader.defines = key.2.clone();
+        if cfg!(debug_assertions) && !shader.defines.iter().any(|(name, _)| name == "DEBUG") {
+            shader.defines.push(("DEBUG".to_owned(), "1".to_owned()));
+        }
+
+        if let Err(err) = shader.reload(key, roots) {
+            println!("{}", err.red());
+        }
+
+        Ok(shader)
+    }
+
+    /// Keeps the old program if the new one doesn't work.
+    fn reload(
+        &mut self,
+        (vert_path, frag_path, _): &Self::Key,
+        roots: &Roots,
+    ) -> Result<(), String> {
+        // a mod could have added or removed one of them
+        self.vert_path = roots.path(vert_path);
+        self.frag_path = roots.path(frag_path);
+        self.include_dirs = roots.candidates("shaders");
+
+        self.compile_program()
+    }
+
+    fn files(&self, (vert_path, frag_path, _): &Self::Key, roots: &Roots) -> Vec<PathBuf> {
+        let mut files = self.files.clone();
+        // even if they couldn't be loaded, to try again when they're fixed
+        for path in roots
+            .candidates(vert_path)
+            .into_iter()
+            .chain(roots.candidates(frag_path))
+        {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+        files
     }
 }
 
@@ -270,26 +534,29 @@ impl Drop for Shader {
 #[derive(Default)]
 pub struct Texture {
     path: PathBuf,
+    meta_path: PathBuf,
     native: Option<u32>,
-    current_version: u64, // for auto-reload
+    /// The options set in code, the `.meta` file goes on top.
+    code_options: TextureOptions,
+    /// The options the current texture was loaded with.
+    options: TextureOptions,
+    /// The size of the image in pixels.
+    size: (u32, u32),
 }
 
 impl Texture {
-    /// Creates a fragment shader.
-    pub fn new(content: &mut Content, path: &str) -> Texture {
-        let mut buf = PathBuf::new();
-        buf.push(&content.base_path);
-        buf.push(path);
-        buf = buf.canonicalize().unwrap();
-
-        &content.resource_versions.insert(buf.clone(), 0);
-
-        println!("Registering: {:?}", &buf);
+    pub fn new(content: &mut Content, path: &str) -> Result<Handle<Texture>, String> {
+        Texture::with_options(content, path, TextureOptions::default())
+    }
 
-        Texture {
-            path: buf,
-            ..Default::default()
-        }
+    /// Loads a texture, the `.meta` file next to the image can change the
+    /// options. The same image with other options is another texture.
+    pub fn with_options(
+        content: &mut Content,
+        path: &str,
+        options: TextureOptions,
+    ) -> Result<Handle<Texture>, String> {
+        content.load((path.to_owned(), options))
     }
 
     pub fn reset() {
@@ -298,60 +565,92 @@ impl Texture {
         }
     }
 
-    pub fn load(&mut self) -> Result<(), String> {
-        println!("Loading: {:?}", &self.path);
+    /// The texture coordinates of a part of the image in pixels, as left, top,
+    /// right, bottom. Mirrored horizontally with `flip`.
+    pub fn tex_coords(&self, rect: &Rect, flip: bool) -> (f32, f32, f32, f32) {
+        let (width, height) = (self.size.0.max(1) as f64, self.size.1.max(1) as f64);
+        let (left, right) = (rect.x / width, rect.right() / width);
+        let (top, bottom) = (rect.y / height, rect.bottom() / height);
+
+        if flip {
+            (right as f32, top as f32, left as f32, bottom as f32)
+        } else {
+            (left as f32, top as f32, right as f32, bottom as f32)
+        }
+    }
 
-        let decoder = png::Decoder::new(File::open(&self.path).unwrap());
-        // .map_err(|err| format!("{} when loading {}", err, self.path.to_sThe glGenTextures function is only available in OpenGL version 1.1 or later.tr().unwrap()))?;
+    /// The options the texture was loaded with, including the `.meta` file.
+    pub fn options(&self) -> TextureOptions {
+        self.options
+    }
 
-        let (info, mut reader) = decoder.read_info().unwrap();
-        let mut buf = vec![0; info.buffer_size()];
-        reader.next_frame(&mut buf).unwrap();
+    /// Loads the image, see `image::load` for the supported formats, with the
+    /// options from the `.meta` file next to it if there is one. Keeps the old
+    /// texture if that fails.
+    fn load_image(&mut self) -> Result<(), String> {
+        let options = match std::fs::read_to_string(&self.meta_path) {
+            Ok(src) => self
+                .code_options
+                .parse(&src)
+                .map_err(|err| format!("{} in {}", err, self.meta_path.display()))?,
+            Err(_) => self.code_options,
+        };
+
+        let mut image = image::load(&self.path)?;
+        if options.premultiply {
+            image.premultiply();
+        }
 
         // Delete old texture.
         self.delete_texture();
 
-        println!("{} {}", info.width, info.height);
-
-        // Load new shader.
+        let (min_filter, mag_filter) = match (options.filter, options.mipmaps) {
+            (Filter::Nearest, false) => (gl::NEAREST, gl::NEAREST),
+            (Filter::Nearest, true) => (gl::NEAREST_MIPMAP_LINEAR, gl::NEAREST),
+            (Filter::Linear, false) => (gl::LINEAR, gl::LINEAR),
+            (Filter::Linear, true) => (gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR),
+        };
+        let wrap = match options.wrap {
+            Wrap::Clamp => gl::CLAMP_TO_EDGE,
+            Wrap::Repeat => gl::REPEAT,
+        };
+
+        // Load new texture.
         unsafe {
-            let tex: GLuint = 0;
-            gl::GenTextures(1, transmute(&tex));
+            let mut tex: GLuint = 0;
+            gl::GenTextures(1, &mut tex);
             gl::BindTexture(gl::TEXTURE_2D, tex);
 
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, 0x2601);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, 0x2601);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag_filter as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as GLint);
 
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
                 gl::RGBA as i32,
-                info.width as i32,
-                info.height as i32,
+                image.width as i32,
+                image.height as i32,
                 0,
                 gl::RGBA,
                 gl::UNSIGNED_BYTE,
-                transmute(buf.as_mut_ptr()),
+                image.pixels.as_ptr() as *const _,
             );
 
+            if options.mipmaps {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+
             self.native = Some(tex);
         }
+        self.options = options;
+        self.size = (image.width, image.height);
 
         Ok(())
     }
 
-    pub fn select(&mut self, content: &mut Content) {
-        // println!("Selecting: {:?}", &self.path);
-        match content.resource_versions.get(&self.path) {
-            Some(new_version) => {
-                if *new_version != self.current_version {
-                    self.load().unwrap();
-                    self.current_version = *new_version;
-                }
-            }
-            _ => (),
-        }
-
+    pub fn select(&self) {
         match self.native {
             Some(native) => unsafe {
                 gl::BindTexture(gl::TEXTURE_2D, native);
@@ -371,24 +670,352 @@ impl Texture {
     }
 }
 
+impl Asset for Texture {
+    /// The image and the options set in code.
+    type Key = (String, TextureOptions);
+
+    fn load(key: &(String, TextureOptions), roots: &Roots) -> Result<Texture, String> {
+        let mut texture = Texture::default();
+        texture.code_options = key.1;
+        texture.options = key.1;
+        texture.reload(key, roots)?;
+
+        Ok(texture)
+    }
+
+    fn reload(
+        &mut self,
+        (path, _): &(String, TextureOptions),
+        roots: &Roots,
+    ) -> Result<(), String> {
+        // the image and its `.meta` file can come from different mods
+        self.path = roots.path(path);
+        self.meta_path = roots.path(&format!("{}.meta", path));
+
+        self.load_image()
+    }
+
+    fn files(&self, (path, _): &(String, TextureOptions), roots: &Roots) -> Vec<PathBuf> {
+        let mut files = roots.candidates(path);
+        files.extend(roots.candidates(&format!("{}.meta", path)));
+        files
+    }
+}
+
 impl Drop for Texture {
     fn drop(&mut self) {
         self.delete_texture();
     }
 }
 
-pub struct Renderer {}
+impl Asset for Map {
+    type Key = String;
+
+    fn load(path: &String, roots: &Roots) -> Result<Map, String> {
+        Map::load(&roots.path(path))
+    }
+
+    fn files(&self, path: &String, roots: &Roots) -> Vec<PathBuf> {
+        roots.candidates(path)
+    }
+}
+
+impl Asset for Sheet {
+    type Key = String;
+
+    fn load(path: &String, roots: &Roots) -> Result<Sheet, String> {
+        Sheet::load(&roots.path(path))
+    }
+
+    fn files(&self, path: &String, roots: &Roots) -> Vec<PathBuf> {
+        roots.candidates(path)
+    }
+}
+
+impl Asset for Tileset {
+    type Key = String;
+
+    fn load(path: &String, roots: &Roots) -> Result<Tileset, String> {
+        Tileset::load(&roots.path(path))
+    }
+
+    fn files(&self, path: &String, roots: &Roots) -> Vec<PathBuf> {
+        roots.candidates(path)
+    }
+}
+
+/// The sample rate sounds are converted to and played at.
+const SAMPLE_RATE: i32 = 44100;
+
+/// A WAV file, converted to 16-bit mono at `SAMPLE_RATE` when it's loaded.
+pub struct Sound {
+    samples: Vec<i16>,
+}
+
+impl Sound {
+    pub fn new(content: &mut Content, path: &str) -> Result<Handle<Sound>, String> {
+        content.load(path.to_owned())
+    }
+
+    fn load_wav(path: &Path) -> Result<Sound, String> {
+        let error = |err: String| format!("{} when loading {}", err, path.display());
+        let wav = AudioSpecWAV::load_wav(path).map_err(error)?;
+        let cvt = AudioCVT::new(
+            wav.format,
+            wav.channels,
+            wav.freq,
+            AudioFormat::s16_sys(),
+            1,
+            SAMPLE_RATE,
+        )
+        .map_err(error)?;
+
+        let bytes = cvt.convert(wav.buffer().to_vec());
+        Ok(Sound {
+            samples: bytes
+                .chunks(2)
+                .map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+                .collect(),
+        })
+    }
+}
+
+impl Asset for Sound {
+    type Key = String;
+
+    fn load(path: &String, roots: &Roots) -> Result<Sound, String> {
+        Sound::load_wav(&roots.path(path))
+    }
+
+    fn files(&self, path: &String, roots: &Roots) -> Vec<PathBuf> {
+        roots.candidates(path)
+    }
+}
+
+/// Plays one sound at a time, a new one cuts off the one that's playing.
+pub struct Audio {
+    queue: AudioQueue<i16>,
+}
+
+impl Audio {
+    pub fn new(sdl: &Sdl) -> Result<Audio, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = sdl.audio()?.open_queue(None, &spec)?;
+        queue.resume();
+
+        Ok(Audio { queue })
+    }
+
+    pub fn play(&self, sound: &Sound) {
+        self.queue.clear();
+        self.queue.queue(&sound.samples);
+    }
+}
+
+/// A sprite sheet with its texture, see `sprite::Sheet`.
+pub struct Sprite {
+    pub sheet: Handle<Sheet>,
+    pub texture: Handle<Texture>,
+    /// The image `texture` was loaded from.
+    texture_path: String,
+}
+
+impl Sprite {
+    pub fn new(content: &mut Content, path: &str) -> Result<Sprite, String> {
+        let sheet = content.load::<Sheet>(path.to_owned())?;
+        let texture_path = content.get(&sheet).texture.clone();
+
+        Ok(Sprite {
+            texture: Texture::new(content, &texture_path)?,
+            sheet,
+            texture_path,
+        })
+    }
+
+    /// Switches to another texture if the reloaded sheet points to one.
+    pub fn update(&mut self, content: &mut Content) {
+        update_texture(
+            content,
+            &content.get(&self.sheet).texture.clone(),
+            &mut self.texture,
+            &mut self.texture_path,
+        );
+    }
+
+    /// The texture coordinates of a frame, mirrored horizontally with `flip`.
+    pub fn tex_coords(&self, content: &Content, frame: usize, flip: bool) -> (f32, f32, f32, f32) {
+        match content.get(&self.sheet).frames.get(frame) {
+            Some(frame) => content.get(&self.texture).tex_coords(frame, flip),
+            None => (0.0, 0.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// A tileset with its texture, see `tileset::Tileset`.
+pub struct Tiles {
+    pub tileset: Handle<Tileset>,
+    pub texture: Handle<Texture>,
+    /// The image `texture` was loaded from.
+    texture_path: String,
+}
+
+impl Tiles {
+    pub fn new(content: &mut Content, path: &str) -> Result<Tiles, String> {
+        let tileset = content.load::<Tileset>(path.to_owned())?;
+        let texture_path = content.get(&tileset).texture.clone();
+
+        Ok(Tiles {
+            texture: Texture::new(content, &texture_path)?,
+            tileset,
+            texture_path,
+        })
+    }
+
+    /// Switches to another texture if the reloaded tileset points to one.
+    pub fn update(&mut self, content: &mut Content) {
+        update_texture(
+            content,
+            &content.get(&self.tileset).texture.clone(),
+            &mut self.texture,
+            &mut self.texture_path,
+        );
+    }
+}
+
+/// Loads the texture at `path` if it isn't the one at `current_path`. Keeps the
+/// old one if that fails.
+fn update_texture(
+    content: &mut Content,
+    path: &str,
+    texture: &mut Handle<Texture>,
+    current_path: &mut String,
+) {
+    if path == current_path {
+        return;
+    }
+
+    match Texture::new(content, path) {
+        Ok(new_texture) => *texture = new_texture,
+        Err(err) => println!("Error loading texture: {}", err.red()),
+    }
+    // don't try again every frame
+    *current_path = path.to_owned();
+}
+
+/// A vertex as it is stored in the vertex buffer, see `ATTRIBUTES` for
+/// where shaders find each field.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct Vertex {
+    pos: [f32; 2],
+    tex_coord: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Collects everything drawn into one vertex buffer and draws it with a single
+/// call. The batch is drawn whenever the shader or texture changes and by
+/// `flush`, so they have to be changed through the renderer.
+pub struct Renderer {
+    vao: GLuint,
+    vbo: GLuint,
+    vertices: Vec<Vertex>,
+    color: [f32; 4],
+    /// Set on every shader the renderer switches to.
+    standard_uniforms: Vec<(&'static str, Uniform)>,
+}
 
 impl Renderer {
     pub fn new() -> Renderer {
+        let mut vao = 0;
+        let mut vbo = 0;
+
         unsafe {
-            gl::PushMatrix();
-            gl::LoadIdentity();
-            gl::Scaled(2.0 / 16.0, -2.0 / 9.0, 1.0);
-            gl::Translated(-8.0, -4.5, 1.0);
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let stride = size_of::<Vertex>() as GLsizei;
+            let float = size_of::<f32>();
+            gl::EnableVertexAttribArray(ATTR_POS);
+            gl::VertexAttribPointer(ATTR_POS, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(ATTR_TEX_COORD);
+            gl::VertexAttribPointer(
+                ATTR_TEX_COORD,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                (2 * float) as *const _,
+            );
+            gl::EnableVertexAttribArray(ATTR_COLOR);
+            gl::VertexAttribPointer(
+                ATTR_COLOR,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                (4 * float) as *const _,
+            );
+
+            gl::BindVertexArray(0);
         }
 
-        Renderer {}
+        Renderer {
+            vao,
+            vbo,
+            vertices: Vec::new(),
+            color: [1.0; 4],
+            standard_uniforms: Vec::new(),
+        }
+    }
+
+    /// Starts a frame, drawing in map coordinates looking at what `camera`
+    /// sees. Shaders get these uniforms:
+    ///
+    /// - `viewMatrix`, `projMatrix`: map to screen coordinates
+    /// - `camera`: the center of the view in tiles
+    /// - `view`: the visible part of the map in tiles, x, y, width and height
+    /// - `resolution`: the size of the viewport in pixels
+    /// - `time`: `time` in seconds, usually since the game started
+    pub fn begin(&mut self, camera: &Camera, resolution: (i32, i32), time: f64) {
+        self.vertices.clear();
+        self.color = [1.0; 4];
+
+        let mut view_matrix = identity();
+        view_matrix[12] = -camera.x as f32;
+        view_matrix[13] = -camera.y as f32;
+
+        // y goes down in the map
+        let mut proj_matrix = identity();
+        proj_matrix[0] = 2.0 / camera.width as f32;
+        proj_matrix[5] = -2.0 / camera.height as f32;
+
+        let view = camera.view();
+
+        self.standard_uniforms = vec![
+            ("viewMatrix", Uniform::Mat4(view_matrix)),
+            ("projMatrix", Uniform::Mat4(proj_matrix)),
+            ("camera", Uniform::Vec2(camera.x as f32, camera.y as f32)),
+            (
+                "view",
+                Uniform::Vec4(
+                    view.x as f32,
+                    view.y as f32,
+                    view.width as f32,
+                    view.height as f32,
+                ),
+            ),
+            (
+                "resolution",
+                Uniform::Vec2(resolution.0 as f32, resolution.1 as f32),
+            ),
+            ("time", Uniform::Float(time as f32)),
+        ];
     }
 
     pub fn clear(&self, r: f32, g: f32, b: f32) {
@@ -398,43 +1025,119 @@ impl Renderer {
         }
     }
 
-    pub fn rgb(&self, r: f32, g: f32, b: f32) {
+    /// Draws the batch and switches to `shader`. Blending goes back to normal
+    /// until the next `texture`, so untextured draws aren't blended like a
+    /// premultiplied texture.
+    pub fn shader(&mut self, shader: &mut Shader) {
+        self.flush();
+        for &(name, value) in &self.standard_uniforms {
+            shader.set(name, value);
+        }
+        shader.select();
+        Renderer::blend(false);
+    }
+
+    /// Draws the batch and switches to `texture`.
+    pub fn texture(&mut self, texture: &Texture) {
+        self.flush();
         unsafe {
-            gl::Color3f(r, g, b);
+            gl::ActiveTexture(gl::TEXTURE0 + TEXTURE_UNIT as GLenum);
         }
+        texture.select();
+        Renderer::blend(texture.options().premultiply);
     }
 
-    pub fn rect(&self, rect: &Rect) {
+    fn blend(premultiplied: bool) {
+        // premultiplied colors already have alpha applied
+        let source = if premultiplied {
+            gl::ONE
+        } else {
+            gl::SRC_ALPHA
+        };
         unsafe {
-            gl::Begin(gl::QUADS);
-            gl::TexCoord2d(0.0, 0.0);
-            gl::Vertex2d(rect.x, rect.y);
-            gl::TexCoord2d(1.0, 0.0);
-            gl::Vertex2d(rect.x + rect.width, rect.y);
-            gl::TexCoord2d(1.0, 1.0);
-            gl::Vertex2d(rect.x + rect.width, rect.y + rect.height);
-            gl::TexCoord2d(0.0, 1.0);
-            gl::Vertex2d(rect.x, rect.y + rect.height);
-            gl::End();
+            gl::BlendFunc(source, gl::ONE_MINUS_SRC_ALPHA);
         }
     }
 
-    pub fn rect2(&self, x: f64, y: f64, w: f64, h: f64) {
+    pub fn rgb(&mut self, r: f32, g: f32, b: f32) {
+        self.color = [r, g, b, 1.0];
+    }
+
+    pub fn rect(&mut self, rect: &Rect) {
+        self.rect_uv(rect, (0.0, 0.0, 1.0, 1.0));
+    }
+
+    /// A rect showing part of the texture, given as left, top, right, bottom
+    /// texture coordinates, e.g. from `Sprite::tex_coords`.
+    pub fn rect_uv(&mut self, rect: &Rect, (u0, v0, u1, v1): (f32, f32, f32, f32)) {
+        let (x, y, right, bottom) = (rect.x, rect.y, rect.right(), rect.bottom());
+
+        self.vertex(x, y, u0, v0);
+        self.vertex(right, y, u1, v0);
+        self.vertex(right, bottom, u1, v1);
+        self.vertex(x, y, u0, v0);
+        self.vertex(right, bottom, u1, v1);
+        self.vertex(x, bottom, u0, v1);
+    }
+
+    pub fn rect2(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        self.rect(&Rect::new(x, y, w, h));
+    }
+
+    /// A convex polygon, without texture coordinates.
+    pub fn polygon(&mut self, points: &[(f64, f64)]) {
+        for i in 1..points.len().saturating_sub(1) {
+            for &(x, y) in &[points[0], points[i], points[i + 1]] {
+                self.vertex(x, y, 0.0, 0.0);
+            }
+        }
+    }
+
+    fn vertex(&mut self, x: f64, y: f64, u: f32, v: f32) {
+        self.vertices.push(Vertex {
+            pos: [x as f32, y as f32],
+            tex_coord: [u, v],
+            color: self.color,
+        });
+    }
+
+    /// Draws everything since the last flush with the current shader and
+    /// texture.
+    pub fn flush(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
         unsafe {
-            gl::Begin(gl::POLYGON);
-            gl::Vertex2d(x, y);
-            gl::Vertex2d(x + w, y);
-            gl::Vertex2d(x + w, y + h);
-            gl::Vertex2d(x, y + h);
-            gl::End();
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * size_of::<Vertex>()) as GLsizeiptr,
+                self.vertices.as_ptr() as *const _,
+                gl::STREAM_DRAW,
+            );
+            gl::DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as GLsizei);
+            gl::BindVertexArray(0);
         }
+
+        self.vertices.clear();
+    }
+}
+
+fn identity() -> [f32; 16] {
+    let mut matrix = [0.0; 16];
+    for i in 0..4 {
+        matrix[i * 5] = 1.0;
     }
+    matrix
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
         unsafe {
-            gl::PopMatrix();
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
         }
     }
 }
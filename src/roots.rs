@@ -0,0 +1,184 @@
+use options::Options;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Sets the content directory when there's no `--content`.
+pub const CONTENT_VAR: &str = "GAEM_CONTENT";
+
+/// The directories content is loaded from. The first one has the base game,
+/// the ones after it (mods, DLC) replace the files that are in them.
+#[derive(Debug, Clone)]
+pub struct Roots {
+    dirs: Vec<PathBuf>,
+}
+
+impl Roots {
+    /// The content directory is the first of:
+    ///
+    /// 1. `--content`
+    /// 2. the `GAEM_CONTENT` environment variable
+    /// 3. a `content` directory next to the executable or above it, which
+    ///    finds the repository's content from `target/debug`
+    /// 4. a `content` directory in the working directory or above it
+    ///
+    /// Every `--mod` goes on top of it, later ones win.
+    pub fn find(options: &Options) -> Result<Roots, String> {
+        let base = match options.content {
+            Some(ref dir) => dir.clone(),
+            None => match env::var_os(CONTENT_VAR) {
+                Some(dir) => PathBuf::from(dir),
+                None => search().ok_or(
+                    "Couldn't find the content directory, set it with --content or GAEM_CONTENT",
+                )?,
+            },
+        };
+
+        let mut dirs = vec![base];
+        dirs.extend(options.mods.iter().cloned());
+        for dir in &dirs {
+            if !dir.is_dir() {
+                Err(format!("Content directory {} doesn't exist", dir.display()))?
+            }
+        }
+
+        Ok(Roots {
+            dirs: dirs.iter().map(|dir| canonical(dir)).collect(),
+        })
+    }
+
+    #[cfg(feature = "window")]
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// The file at `path` in the last directory that has it, in the content
+    /// directory if none does.
+    pub fn path(&self, path: &str) -> PathBuf {
+        let candidates = self.candidates(path);
+        let found = candidates.iter().rev().find(|file| file.exists());
+
+        found.unwrap_or(&candidates[0]).clone()
+    }
+
+    /// `path` in every directory, content directory first.
+    pub fn candidates(&self, path: &str) -> Vec<PathBuf> {
+        self.dirs
+            .iter()
+            .map(|dir| canonical(&dir.join(path)))
+            .collect()
+    }
+}
+
+fn search() -> Option<PathBuf> {
+    let exe = env::current_exe()
+        .ok()
+        .and_then(|exe| canonical(&exe).parent().map(Path::to_path_buf));
+    let cwd = env::current_dir().ok();
+
+    exe.iter()
+        .chain(cwd.iter())
+        .flat_map(|dir| dir.ancestors())
+        .map(|dir| dir.join("content"))
+        .find(|dir| dir.is_dir())
+}
+
+/// Canonicalizes `path`, or only its directory if the file doesn't exist, so
+/// removed and not yet created files get the same path as existing ones.
+pub fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => match dir.canonicalize() {
+            Ok(dir) => dir.join(name),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    /// An empty directory in the temp directory, with a `which` file that
+    /// contains `name`.
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gaem-roots-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("which"), name).unwrap();
+        dir
+    }
+
+    fn which(roots: &Roots, path: &str) -> String {
+        fs::read_to_string(roots.path(path)).unwrap()
+    }
+
+    #[test]
+    fn finds_the_content_directory() {
+        let options = Options {
+            content: Some(dir("option")),
+            ..Options::default()
+        };
+        let from_env = dir("env");
+
+        // the only test that changes the variable
+        env::set_var(CONTENT_VAR, &from_env);
+        assert_eq!(which(&Roots::find(&options).unwrap(), "which"), "option");
+        assert_eq!(
+            which(&Roots::find(&Options::default()).unwrap(), "which"),
+            "env"
+        );
+
+        env::set_var(CONTENT_VAR, from_env.join("missing"));
+        assert!(Roots::find(&Options::default()).is_err());
+
+        // the repository's content from the executable in target/
+        env::remove_var(CONTENT_VAR);
+        let roots = Roots::find(&Options::default()).unwrap();
+        assert_eq!(
+            roots.path("levels/default.level"),
+            canonical(Path::new("content/levels/default.level"))
+        );
+    }
+
+    #[test]
+    fn later_mods_win() {
+        let (base, first, second) = (dir("base"), dir("first"), dir("second"));
+        for (dir, files) in &[(&base, "abc"), (&first, "bc"), (&second, "c")] {
+            for file in files.chars() {
+                fs::copy(dir.join("which"), dir.join(file.to_string())).unwrap();
+            }
+        }
+
+        let options = Options {
+            content: Some(base.clone()),
+            mods: vec![first.clone(), second.clone()],
+            ..Options::default()
+        };
+        let roots = Roots::find(&options).unwrap();
+
+        assert_eq!(which(&roots, "a"), "base");
+        assert_eq!(which(&roots, "b"), "first");
+        assert_eq!(which(&roots, "c"), "second");
+        assert_eq!(roots.path("d"), canonical(&base.join("d")));
+        assert_eq!(
+            roots.candidates("c"),
+            vec![
+                canonical(&base.join("c")),
+                canonical(&first.join("c")),
+                canonical(&second.join("c")),
+            ]
+        );
+
+        let options = Options {
+            mods: vec![base.join("missing")],
+            ..options
+        };
+        assert!(Roots::find(&options).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use rect::Rect;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Frames in a texture and the animations made from them.
+///
+/// Frames are rects in pixels, numbered in the order they're defined. `grid`
+/// adds frames of the same size, left to right and top to bottom, `frame` adds
+/// one anywhere in the image. An animation lists frames with how long each is
+/// shown in seconds, and either loops or stops on the last frame.
+///
+/// ```text
+/// texture textures/player.png
+/// grid 32 32 4 8        # frame width and height, columns, frame count
+/// frame 0 64 48 32      # x y width height
+/// animation idle loop 0:0.5 1:0.5
+/// animation land once 8:0.1 0:0.1
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sheet {
+    /// The image, relative to the content directory.
+    pub texture: String,
+    pub frames: Vec<Rect>,
+    pub animations: HashMap<String, Animation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// Frame indices and how long they're shown.
+    pub frames: Vec<(usize, f64)>,
+    pub looping: bool,
+}
+
+impl Sheet {
+    pub fn load(path: &Path) -> Result<Sheet, String> {
+        let src = fs::read_to_string(path)
+            .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+
+        Sheet::parse(&src).map_err(|err| format!("{} in {}", err, path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<Sheet, String> {
+        let mut texture = None;
+        let mut frames = Vec::new();
+        let mut animations = HashMap::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = || format!("Invalid sheet line {}: {}", i + 1, line.trim());
+            let number = |word: &str| word.parse::<f64>().map_err(|_| error());
+            let count = |word: &str| word.parse::<usize>().map_err(|_| error());
+
+            match words.as_slice() {
+                [] => (),
+                ["texture", path] => texture = Some(path.to_string()),
+                ["grid", width, height, columns, total] => {
+                    let (width, height) = (number(width)?, number(height)?);
+                    let columns = count(columns)?.max(1);
+                    for frame in 0..count(total)? {
+                        let (col, row) = (frame % columns, frame / columns);
+                        frames.push(Rect::new(
+                            col as f64 * width,
+                            row as f64 * height,
+                            width,
+                            height,
+                        ));
+                    }
+                }
+                ["frame", x, y, width, height] => {
+                    frames.push(Rect::new(
+                        number(x)?,
+                        number(y)?,
+                        number(width)?,
+                        number(height)?,
+                    ));
+                }
+                ["animation", name, mode, animation_frames @ ..] => {
+                    let looping = match *mode {
+                        "loop" => true,
+                        "once" => false,
+                        _ => Err(error())?,
+                    };
+
+                    let mut animation = Animation {
+                        frames: Vec::new(),
+                        looping,
+                    };
+                    for frame in animation_frames {
+                        let (index, duration) = frame.split_once(':').ok_or_else(error)?;
+                        let (index, duration) = (count(index)?, number(duration)?);
+                        if index >= frames.len() || duration <= 0.0 {
+                            Err(error())?
+                        }
+                        animation.frames.push((index, duration));
+                    }
+                    if animation.frames.is_empty() {
+                        Err(error())?
+                    }
+
+                    animations.insert(name.to_string(), animation);
+                }
+                _ => Err(error())?,
+            }
+        }
+
+        Ok(Sheet {
+            texture: texture.ok_or("Missing texture")?,
+            frames,
+            animations,
+        })
+    }
+}
+
+impl Animation {
+    /// The frame that is shown `time` seconds after the animation started.
+    pub fn frame_at(&self, time: f64) -> usize {
+        let duration: f64 = self.frames.iter().map(|&(_, duration)| duration).sum();
+        let mut time = if self.looping { time % duration } else { time };
+
+        for &(frame, duration) in &self.frames {
+            if time < duration {
+                return frame;
+            }
+            time -= duration;
+        }
+
+        self.frames[self.frames.len() - 1].0
+    }
+}
+
+/// Plays the animations of a sheet, starting over whenever it switches to a
+/// different one.
+#[derive(Debug, Clone, Default)]
+pub struct Animator {
+    animation: String,
+    time: f64,
+}
+
+impl Animator {
+    pub fn play(&mut self, animation: &str) {
+        if self.animation != animation {
+            self.animation = animation.to_owned();
+            self.time = 0.0;
+        }
+    }
+
+    pub fn update(&mut self, time_passed: f64) {
+        self.time += time_passed;
+    }
+
+    /// The current frame, the first one of the sheet if it doesn't have the
+    /// animation.
+    pub fn frame(&self, sheet: &Sheet) -> usize {
+        sheet
+            .animations
+            .get(&self.animation)
+            .map_or(0, |animation| animation.frame_at(self.time))
+    }
+}
@@ -0,0 +1,201 @@
+/// Every kind of tile a map can contain. The discriminant is the id used in
+/// level files and by the Tiled importer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Tile {
+    #[default]
+    Empty = 0,
+    Solid = 1,
+    Hazard = 2,
+    Ice = 3,
+    Bouncy = 4,
+    Ladder = 5,
+    OneWay = 6,
+    /// 45° slopes.
+    SlopeUp = 7,
+    SlopeDown = 8,
+    /// Half slopes, two tiles long per tile of height, so about 26.57°. The
+    /// low tile is the bottom half of the ramp, the high one the top half.
+    HalfSlopeUpLow = 9,
+    HalfSlopeUpHigh = 10,
+    HalfSlopeDownHigh = 11,
+    HalfSlopeDownLow = 12,
+}
+
+/// What a kind of tile does. Collision, physics and rendering only look at
+/// these, never at the tile itself.
+pub struct TileDef {
+    /// The name used in level legends.
+    pub name: &'static str,
+    /// Blocks movement from every side.
+    pub solid: bool,
+    /// Can be jumped through from below and landed on from above.
+    pub one_way: bool,
+    /// The height of the surface at the left and right edge of the tile for
+    /// slopes, from 0.0 (bottom of the tile) to 1.0 (top of the tile).
+    pub slope: Option<(f64, f64)>,
+    /// Sends the player back to the spawn when touched.
+    pub hazard: bool,
+    /// How much grip the player has when standing on the tile, 1.0 is normal.
+    pub friction: f64,
+    /// Jump height in tiles the player is launched with when landing on the
+    /// tile, 0.0 for no bounce.
+    pub bounce: f64,
+    /// The player can climb up while overlapping the tile.
+    pub climbable: bool,
+    /// The color the tile is drawn with, `None` if it isn't drawn at all.
+    #[cfg_attr(not(feature = "window"), allow(dead_code))]
+    pub color: Option<(f32, f32, f32)>,
+}
+
+const DEFAULT: TileDef = TileDef {
+    name: "",
+    solid: false,
+    one_way: false,
+    slope: None,
+    hazard: false,
+    friction: 1.0,
+    bounce: 0.0,
+    climbable: false,
+    color: None,
+};
+
+// indexed by the tile id
+const TILES: &[TileDef] = &[
+    TileDef {
+        name: "empty",
+        ..DEFAULT
+    },
+    TileDef {
+        name: "solid",
+        solid: true,
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "hazard",
+        hazard: true,
+        color: Some((0.8, 0.1, 0.1)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "ice",
+        solid: true,
+        friction: 0.15,
+        color: Some((0.6, 0.85, 1.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "bouncy",
+        solid: true,
+        bounce: 5.0,
+        color: Some((0.3, 0.8, 0.2)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "ladder",
+        climbable: true,
+        color: Some((0.55, 0.35, 0.15)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "one_way",
+        one_way: true,
+        color: Some((0.35, 0.35, 0.35)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "slope_up",
+        slope: Some((0.0, 1.0)),
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "slope_down",
+        slope: Some((1.0, 0.0)),
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "half_slope_up_low",
+        slope: Some((0.0, 0.5)),
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "half_slope_up_high",
+        slope: Some((0.5, 1.0)),
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "half_slope_down_high",
+        slope: Some((1.0, 0.5)),
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+    TileDef {
+        name: "half_slope_down_low",
+        slope: Some((0.5, 0.0)),
+        color: Some((0.0, 0.0, 0.0)),
+        ..DEFAULT
+    },
+];
+
+const ALL: &[Tile] = &[
+    Tile::Empty,
+    Tile::Solid,
+    Tile::Hazard,
+    Tile::Ice,
+    Tile::Bouncy,
+    Tile::Ladder,
+    Tile::OneWay,
+    Tile::SlopeUp,
+    Tile::SlopeDown,
+    Tile::HalfSlopeUpLow,
+    Tile::HalfSlopeUpHigh,
+    Tile::HalfSlopeDownHigh,
+    Tile::HalfSlopeDownLow,
+];
+
+impl TileDef {
+    /// How high the tile is filled at `x` (0.0 is the left edge, 1.0 the
+    /// right edge), from 0.0 for nothing to 1.0 for all of it.
+    pub fn height_at(&self, x: f64) -> f64 {
+        match self.slope {
+            Some((left, right)) => left + (right - left) * x.clamp(0.0, 1.0),
+            None if self.solid => 1.0,
+            None => 0.0,
+        }
+    }
+
+    /// The angle of the surface in radians, positive if it goes up to the
+    /// right.
+    pub fn slope_angle(&self) -> f64 {
+        match self.slope {
+            Some((left, right)) => (right - left).atan(),
+            None => 0.0,
+        }
+    }
+}
+
+impl Tile {
+    pub fn from_id(id: u8) -> Option<Tile> {
+        ALL.get(id as usize).cloned()
+    }
+
+    /// Looks up a tile by its name or its id.
+    pub fn from_name(name: &str) -> Option<Tile> {
+        match name.parse() {
+            Ok(id) => Tile::from_id(id),
+            Err(_) => ALL.iter().cloned().find(|tile| tile.def().name == name),
+        }
+    }
+
+    pub fn def(self) -> &'static TileDef {
+        &TILES[self as usize]
+    }
+
+    pub fn is_solid(self) -> bool {
+        self.def().solid
+    }
+}
@@ -0,0 +1,531 @@
+extern crate serde_json;
+extern crate xml;
+
+use self::serde_json::Value;
+use self::xml::reader::{EventReader, XmlEvent};
+use map::{Map, Trigger};
+use rect::Rect;
+use std::collections::HashMap;
+use tile::Tile;
+
+/// Importer for maps made with the Tiled editor (https://www.mapeditor.org),
+/// exported as either TMX (XML) or JSON.
+///
+/// Tile layers are merged into `Map::blocks`, later layers win. Tiles are
+/// numbered by their position in the first tileset, starting at 1, so the
+/// first tile of the tileset becomes the tile with id 1 (`Tile::Solid`) and
+/// empty cells become `Tile::Empty`. Tiles from other tilesets are errors.
+///
+/// Objects named or typed `spawn` set the player spawn, all other objects
+/// become triggers. Map properties are stored as metadata.
+struct TiledMap {
+    orientation: String,
+    infinite: bool,
+    width: usize,
+    height: usize,
+    tile_width: f64,
+    tile_height: f64,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Layer>,
+    objects: Vec<Object>,
+    properties: HashMap<String, String>,
+}
+
+struct Tileset {
+    name: String,
+    first_gid: u32,
+    /// Missing for external tilesets.
+    tile_count: Option<u32>,
+}
+
+struct Layer {
+    name: String,
+    width: usize,
+    height: usize,
+    gids: Vec<u32>,
+}
+
+#[derive(Default)]
+struct Object {
+    name: String,
+    kind: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Tile objects are positioned by their bottom left corner.
+    is_tile: bool,
+}
+
+// the upper bits of a gid store whether the tile is flipped
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+pub fn parse_json(src: &str) -> Result<Map, String> {
+    let root: Value = serde_json::from_str(src).map_err(|err| format!("Invalid JSON: {}", err))?;
+
+    let mut map = TiledMap {
+        orientation: json_str(&root, "orientation")?,
+        infinite: root["infinite"].as_bool().unwrap_or(false),
+        width: json_num(&root, "width")? as usize,
+        height: json_num(&root, "height")? as usize,
+        tile_width: json_num(&root, "tilewidth")?,
+        tile_height: json_num(&root, "tileheight")?,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+        properties: json_properties(&root),
+    };
+
+    for tileset in root["tilesets"].as_array().into_iter().flatten() {
+        map.tilesets.push(Tileset {
+            name: tileset["name"]
+                .as_str()
+                .or_else(|| tileset["source"].as_str())
+                .unwrap_or("")
+                .to_owned(),
+            first_gid: json_num(tileset, "firstgid")? as u32,
+            tile_count: tileset["tilecount"].as_u64().map(|count| count as u32),
+        });
+    }
+    json_layers(&root, &mut map)?;
+
+    build(map)
+}
+
+fn json_layers(parent: &Value, map: &mut TiledMap) -> Result<(), String> {
+    let layers = match parent["layers"].as_array() {
+        Some(layers) => layers,
+        None => return Ok(()),
+    };
+
+    for layer in layers {
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let name = json_str(layer, "name")?;
+
+                if layer["chunks"].is_array() {
+                    Err("Infinite maps are not supported")?
+                }
+
+                let gids = match layer["data"].as_array() {
+                    Some(data) => data
+                        .iter()
+                        .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                        .collect::<Option<Vec<u32>>>()
+                        .ok_or_else(|| format!("Layer \"{}\" has invalid tile data", name))?,
+                    None => Err(format!(
+                        "Layer \"{}\" isn't CSV encoded, change the tile layer format to CSV",
+                        name
+                    ))?,
+                };
+
+                map.layers.push(Layer {
+                    width: json_num(layer, "width")? as usize,
+                    height: json_num(layer, "height")? as usize,
+                    name,
+                    gids,
+                });
+            }
+            Some("objectgroup") => {
+                for object in layer["objects"].as_array().into_iter().flatten() {
+                    map.objects.push(Object {
+                        name: object["name"].as_str().unwrap_or("").to_owned(),
+                        kind: object["type"]
+                            .as_str()
+                            .or_else(|| object["class"].as_str())
+                            .unwrap_or("")
+                            .to_owned(),
+                        x: json_num(object, "x")?,
+                        y: json_num(object, "y")?,
+                        width: object["width"].as_f64().unwrap_or(0.0),
+                        height: object["height"].as_f64().unwrap_or(0.0),
+                        is_tile: object["gid"].is_u64(),
+                    });
+                }
+            }
+            Some("group") => json_layers(layer, map)?,
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn json_str(value: &Value, key: &str) -> Result<String, String> {
+    value[key]
+        .as_str()
+        .map(|s| s.to_owned())
+        .ok_or_else(|| format!("\"{}\" is missing", key))
+}
+
+fn json_num(value: &Value, key: &str) -> Result<f64, String> {
+    value[key]
+        .as_f64()
+        .ok_or_else(|| format!("\"{}\" is missing", key))
+}
+
+fn json_properties(value: &Value) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+
+    for property in value["properties"].as_array().into_iter().flatten() {
+        if let Some(name) = property["name"].as_str() {
+            let value = match property["value"] {
+                Value::String(ref s) => s.clone(),
+                ref other => other.to_string(),
+            };
+            properties.insert(name.to_owned(), value);
+        }
+    }
+
+    properties
+}
+
+pub fn parse_tmx(src: &str) -> Result<Map, String> {
+    let mut map = TiledMap {
+        orientation: String::new(),
+        infinite: false,
+        width: 0,
+        height: 0,
+        tile_width: 0.0,
+        tile_height: 0.0,
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+        properties: HashMap::new(),
+    };
+
+    // names of the currently open elements
+    let mut stack: Vec<String> = Vec::new();
+    let mut csv = String::new();
+
+    for event in EventReader::new(src.as_bytes()) {
+        match event.map_err(|err| format!("Invalid TMX: {}", err))? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attrs: HashMap<String, String> = attributes
+                    .into_iter()
+                    .map(|attr| (attr.name.local_name, attr.value))
+                    .collect();
+                let attr = |key: &str| attrs.get(key).map_or("", |value| value.as_str());
+                let num = |key: &str| -> Result<f64, String> {
+                    attr(key)
+                        .parse()
+                        .map_err(|_| format!("<{}> has no valid \"{}\"", name.local_name, key))
+                };
+                let parent = stack.last().map_or("", |parent| parent.as_str());
+
+                match (parent, name.local_name.as_str()) {
+                    (_, "map") => {
+                        map.orientation = attr("orientation").to_owned();
+                        map.infinite = attr("infinite") == "1";
+                        map.width = num("width")? as usize;
+                        map.height = num("height")? as usize;
+                        map.tile_width = num("tilewidth")?;
+                        map.tile_height = num("tileheight")?;
+                    }
+                    ("map", "tileset") => map.tilesets.push(Tileset {
+                        name: match attr("name") {
+                            "" => attr("source").to_owned(),
+                            name => name.to_owned(),
+                        },
+                        first_gid: num("firstgid")? as u32,
+                        tile_count: attr("tilecount").parse().ok(),
+                    }),
+                    (_, "layer") => map.layers.push(Layer {
+                        name: attr("name").to_owned(),
+                        width: num("width")? as usize,
+                        height: num("height")? as usize,
+                        gids: Vec::new(),
+                    }),
+                    ("layer", "data") => match attr("encoding") {
+                        "" | "csv" => csv.clear(),
+                        _ => Err(format!(
+                            "Layer \"{}\" isn't CSV encoded, change the tile layer format to CSV",
+                            map.layers.last().unwrap().name
+                        ))?,
+                    },
+                    (_, "data") => Err("<data> outside of a <layer>")?,
+                    ("data", "chunk") => Err("Infinite maps are not supported")?,
+                    ("data", "tile") => {
+                        let layer = map.layers.last_mut().ok_or("<data> outside of a <layer>")?;
+                        // empty tiles have no gid
+                        let gid = match attr("gid") {
+                            "" => 0,
+                            gid => gid.parse().map_err(|_| {
+                                format!("Layer \"{}\" has invalid tile data", layer.name)
+                            })?,
+                        };
+                        layer.gids.push(gid);
+                    }
+                    ("objectgroup", "object") => map.objects.push(Object {
+                        name: attr("name").to_owned(),
+                        kind: match attr("type") {
+                            "" => attr("class").to_owned(),
+                            kind => kind.to_owned(),
+                        },
+                        x: num("x")?,
+                        y: num("y")?,
+                        width: num("width").unwrap_or(0.0),
+                        height: num("height").unwrap_or(0.0),
+                        is_tile: !attr("gid").is_empty(),
+                    }),
+                    ("properties", "property") if stack.len() == 2 => {
+                        map.properties
+                            .insert(attr("name").to_owned(), attr("value").to_owned());
+                    }
+                    _ => (),
+                }
+
+                stack.push(name.local_name);
+            }
+            XmlEvent::Characters(text)
+                if stack.last().map(|name| name.as_str()) == Some("data") =>
+            {
+                csv.push_str(&text)
+            }
+            XmlEvent::EndElement { name } => {
+                stack.pop();
+
+                if name.local_name == "data" && !csv.trim().is_empty() {
+                    let layer = map.layers.last_mut().ok_or("<data> outside of a <layer>")?;
+
+                    for gid in csv.split(',') {
+                        let gid = gid.trim().parse().map_err(|_| {
+                            format!("Layer \"{}\" has invalid tile data", layer.name)
+                        })?;
+                        layer.gids.push(gid);
+                    }
+                    csv.clear();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    build(map)
+}
+
+fn build(mut tiled: TiledMap) -> Result<Map, String> {
+    if tiled.orientation != "orthogonal" {
+        Err(format!(
+            "Only orthogonal maps are supported, this one is {}",
+            tiled.orientation
+        ))?
+    }
+    if tiled.infinite {
+        Err("Infinite maps are not supported")?
+    }
+    if tiled.layers.is_empty() {
+        Err("The map has no tile layers")?
+    }
+
+    tiled.tilesets.sort_by_key(|tileset| tileset.first_gid);
+    let mut blocks = vec![vec![Tile::Empty; tiled.width]; tiled.height];
+
+    for layer in &tiled.layers {
+        if layer.width != tiled.width || layer.height != tiled.height {
+            Err(format!(
+                "Layer \"{}\" is {}x{} but the map is {}x{}",
+                layer.name, layer.width, layer.height, tiled.width, tiled.height
+            ))?
+        }
+        if layer.gids.len() != layer.width * layer.height {
+            Err(format!(
+                "Layer \"{}\" has {} tiles but should have {}",
+                layer.name,
+                layer.gids.len(),
+                layer.width * layer.height
+            ))?
+        }
+
+        for (i, gid) in layer.gids.iter().enumerate() {
+            let gid = gid & !FLIP_FLAGS;
+            if gid == 0 {
+                continue;
+            }
+
+            if tiled.tilesets.is_empty() {
+                Err("The map has no tileset")?
+            }
+
+            let (x, y) = (i % layer.width, i / layer.width);
+            blocks[y][x] = tile(&tiled.tilesets, gid)
+                .map_err(|err| format!("{} at {}, {} in layer \"{}\"", err, x, y, layer.name))?;
+        }
+    }
+
+    let mut map = Map::new(blocks);
+    map.metadata = tiled.properties;
+
+    for object in tiled.objects {
+        let y = if object.is_tile {
+            object.y - object.height
+        } else {
+            object.y
+        };
+        let rect = Rect::new(
+            object.x / tiled.tile_width,
+            y / tiled.tile_height,
+            object.width / tiled.tile_width,
+            object.height / tiled.tile_height,
+        );
+
+        if object.name == "spawn" || object.kind == "spawn" {
+            map.spawn = (rect.x, rect.y);
+        } else {
+            map.triggers.push(Trigger {
+                name: object.name,
+                kind: object.kind,
+                rect,
+            });
+        }
+    }
+
+    Ok(map)
+}
+
+/// The tile for `gid`, which has to be in the first of the sorted `tilesets`.
+fn tile(tilesets: &[Tileset], gid: u32) -> Result<Tile, String> {
+    let contains = |tileset: &Tileset| {
+        gid >= tileset.first_gid && gid - tileset.first_gid < tileset.tile_count.unwrap_or(u32::MAX)
+    };
+
+    let (first, others) = (&tilesets[0], &tilesets[1..]);
+    if let Some(other) = others.iter().find(|tileset| contains(tileset)) {
+        Err(format!(
+            "Tile id {} is from tileset \"{}\", only the first tileset \"{}\" can be used",
+            gid, other.name, first.name
+        ))?
+    }
+
+    if !contains(first) {
+        Err(format!("Unknown tile id {}", gid))?
+    }
+
+    Some(gid - first.first_gid)
+        .filter(|id| *id < 255)
+        .and_then(|id| Tile::from_id(id as u8 + 1))
+        .ok_or_else(|| format!("Unknown tile id {}", gid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn tmx(orientation: &str, body: &str) -> String {
+        format!(
+            r#"<map orientation="{}" width="2" height="2" tilewidth="16" tileheight="16">
+                <tileset firstgid="1" name="tiles" tilecount="3"/>
+                <tileset firstgid="4" name="other" tilecount="2"/>{}</map>"#,
+            orientation, body
+        )
+    }
+
+    fn error(result: Result<Map, String>) -> String {
+        match result {
+            Ok(_) => panic!("the map loaded"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn loads_the_samples() {
+        for path in &["content/levels/tiled.tmx", "content/levels/tiled.json"] {
+            let map = Map::load(Path::new(path)).unwrap();
+
+            assert_eq!((map.width(), map.height()), (16, 9));
+            assert_eq!(map.blocks[8][3], Tile::Solid);
+            assert_eq!(map.blocks[7][10], Tile::Hazard);
+            assert_eq!(map.blocks[1][1], Tile::Empty);
+            assert_eq!(map.spawn, (1.0, 0.0));
+            assert_eq!(map.metadata["name"], "Tiled");
+
+            assert_eq!(map.triggers.len(), 1);
+            let goal = &map.triggers[0];
+            assert_eq!((goal.name.as_str(), goal.kind.as_str()), ("goal", "goal"));
+            assert_eq!((goal.rect.x, goal.rect.y), (12.0, 6.0));
+            assert_eq!((goal.rect.width, goal.rect.height), (2.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn reads_xml_tiles() {
+        let src = tmx(
+            "orthogonal",
+            r#"<layer name="a" width="2" height="2"><data>
+                <tile gid="1"/><tile/><tile/><tile gid="3"/>
+            </data></layer>"#,
+        );
+        let map = parse_tmx(&src).unwrap();
+
+        assert_eq!(map.blocks[0], vec![Tile::Solid, Tile::Empty]);
+        assert_eq!(map.blocks[1], vec![Tile::Empty, Tile::Ice]);
+    }
+
+    #[test]
+    fn rejects_unsupported_maps() {
+        let layer = r#"<layer name="a" width="2" height="2"><data>0,0,0,0</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("isometric", layer))).contains("orthogonal"));
+
+        let base64 = r#"<layer name="a" width="2" height="2">
+            <data encoding="base64">AAAA</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", base64))).contains("CSV"));
+
+        let json = r#"{"orientation": "orthogonal", "width": 2, "height": 2,
+            "tilewidth": 16, "tileheight": 16, "infinite": true, "layers": []}"#;
+        assert!(error(parse_json(json)).contains("Infinite"));
+    }
+
+    #[test]
+    fn rejects_invalid_tiles() {
+        let unknown = r#"<layer name="a" width="2" height="2"><data>0,0,0,200</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", unknown))).contains("Unknown tile id 200"));
+
+        let other = r#"<layer name="a" width="2" height="2"><data>0,0,0,5</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", other))).contains("tileset \"other\""));
+
+        let json = r#"{"orientation": "orthogonal", "width": 1, "height": 1,
+            "tilewidth": 16, "tileheight": 16, "tilesets": [
+                {"firstgid": 1, "name": "tiles", "tilecount": 2},
+                {"firstgid": 10, "source": "other.tsx"}],
+            "layers": [{"type": "tilelayer", "name": "a", "width": 1, "height": 1,
+                "data": [GID]}]}"#;
+        assert!(parse_json(&json.replace("GID", "2")).is_ok());
+        assert!(error(parse_json(&json.replace("GID", "3"))).contains("Unknown tile id 3"));
+        assert!(error(parse_json(&json.replace("GID", "12"))).contains("\"other.tsx\""));
+
+        let csv = r#"<layer name="a" width="2" height="2"><data>0,x,0,0</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", csv))).contains("invalid tile data"));
+
+        let gid = r#"<layer name="a" width="2" height="2"><data>
+            <tile gid="x"/><tile/><tile/><tile/></data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", gid))).contains("invalid tile data"));
+
+        let short = r#"<layer name="a" width="2" height="2"><data>0,0,0</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", short))).contains("has 3 tiles"));
+    }
+
+    #[test]
+    fn rejects_missing_attributes() {
+        let src = r#"<map orientation="orthogonal" width="2" tilewidth="16" tileheight="16"/>"#;
+        assert!(error(parse_tmx(src)).contains("\"height\""));
+
+        let layer = r#"<layer name="a" height="2"><data>0,0,0,0</data></layer>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", layer))).contains("\"width\""));
+
+        let object = r#"<objectgroup><object name="spawn" y="0"/></objectgroup>"#;
+        assert!(error(parse_tmx(&tmx("orthogonal", object))).contains("\"x\""));
+
+        let json = r#"{"orientation": "orthogonal", "width": 2, "height": 2}"#;
+        assert!(error(parse_json(json)).contains("\"tilewidth\""));
+    }
+
+    #[test]
+    fn rejects_data_outside_of_layers() {
+        let src = r#"<map orientation="orthogonal" width="1" height="1" tilewidth="1"
+            tileheight="1"><data>1</data></map>"#;
+        assert_eq!(error(parse_tmx(src)), "<data> outside of a <layer>");
+    }
+}
@@ -0,0 +1,157 @@
+use map::Map;
+use rect::Rect;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tile::Tile;
+
+/// Where the tiles of a map are in a texture. Tiles that aren't in the tileset
+/// are drawn in their plain color.
+///
+/// `tile` uses the same image for every tile of a kind. `autotile` picks the
+/// look of a tile from its neighbours, so levels don't need different tiles
+/// for edges and corners. Every tile is drawn as four quarters, each one from
+/// one of five images in a row of the texture, depending on the neighbours
+/// next to, above or below, and diagonal to that quarter:
+///
+/// 0. outer corners, no neighbours on either side
+/// 1. left and right edges, only a neighbour above or below
+/// 2. top and bottom edges, only a neighbour to the side
+/// 3. inner corners, neighbours on both sides but not diagonally
+/// 4. the inside, surrounded by neighbours
+///
+/// ```text
+/// texture textures/tiles.png
+/// tile_size 16            # in pixels
+/// autotile solid 0        # tile, row
+/// tile ladder 1 3         # tile, column, row
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    /// The image, relative to the content directory.
+    pub texture: String,
+    pub tile_size: f64,
+    tiles: HashMap<Tile, Kind>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Kind {
+    Fixed(usize, usize),
+    Auto(usize),
+}
+
+impl Tileset {
+    pub fn load(path: &Path) -> Result<Tileset, String> {
+        let src = fs::read_to_string(path)
+            .map_err(|err| format!("{} when loading {}", err, path.display()))?;
+
+        Tileset::parse(&src).map_err(|err| format!("{} in {}", err, path.display()))
+    }
+
+    pub fn parse(src: &str) -> Result<Tileset, String> {
+        let mut texture = None;
+        let mut tile_size = 16.0;
+        let mut tiles = HashMap::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = || format!("Invalid tileset line {}: {}", i + 1, line.trim());
+            let tile =
+                |name: &str| Tile::from_name(name).ok_or_else(|| format!("Unknown tile: {}", name));
+            let count = |word: &str| word.parse::<usize>().map_err(|_| error());
+
+            match words.as_slice() {
+                [] => (),
+                ["texture", path] => texture = Some(path.to_string()),
+                ["tile_size", size] => {
+                    tile_size = size.parse().map_err(|_| error())?;
+                    if tile_size <= 0.0 {
+                        Err(error())?
+                    }
+                }
+                ["tile", name, column, row] => {
+                    tiles.insert(tile(name)?, Kind::Fixed(count(column)?, count(row)?));
+                }
+                ["autotile", name, row] => {
+                    tiles.insert(tile(name)?, Kind::Auto(count(row)?));
+                }
+                _ => Err(error())?,
+            }
+        }
+
+        Ok(Tileset {
+            texture: texture.ok_or("Missing texture")?,
+            tile_size,
+            tiles,
+        })
+    }
+
+    pub fn has(&self, tile: Tile) -> bool {
+        self.tiles.contains_key(&tile)
+    }
+
+    /// The parts of the texture to draw the tile at `x`, `y` with, in pixels,
+    /// for its top left, top right, bottom left and bottom right quarter.
+    /// `None` if the tile isn't in the tileset.
+    pub fn quarters(&self, map: &Map, x: usize, y: usize) -> Option<[Rect; 4]> {
+        let tile = map.blocks[y][x];
+        let half = self.tile_size / 2.0;
+        let quarter = |column: usize, row: usize, qx: usize, qy: usize| {
+            Rect::new(
+                column as f64 * self.tile_size + qx as f64 * half,
+                row as f64 * self.tile_size + qy as f64 * half,
+                half,
+                half,
+            )
+        };
+
+        let mut quarters = [Rect::default(); 4];
+        for (i, quarter_rect) in quarters.iter_mut().enumerate() {
+            let (qx, qy) = (i % 2, i / 2);
+
+            *quarter_rect = match *self.tiles.get(&tile)? {
+                Kind::Fixed(column, row) => quarter(column, row, qx, qy),
+                Kind::Auto(row) => {
+                    // towards the neighbours of this quarter
+                    let dx = if qx == 0 { -1 } else { 1 };
+                    let dy = if qy == 0 { -1 } else { 1 };
+                    let (x, y) = (x as i64, y as i64);
+
+                    let side = connects(map, tile, x + dx, y);
+                    let vertical = connects(map, tile, x, y + dy);
+                    let diagonal = connects(map, tile, x + dx, y + dy);
+                    let column = match (side, vertical) {
+                        (false, false) => 0,
+                        (false, true) => 1,
+                        (true, false) => 2,
+                        (true, true) if !diagonal => 3,
+                        (true, true) => 4,
+                    };
+
+                    quarter(column, row, qx, qy)
+                }
+            };
+        }
+
+        Some(quarters)
+    }
+}
+
+/// If `tile` continues into the tile at `x`, `y`. Tiles only connect to the
+/// same kind of tile, solid ones to slopes too, and everything to the outside
+/// of the map.
+fn connects(map: &Map, tile: Tile, x: i64, y: i64) -> bool {
+    if x < 0 || y < 0 {
+        return true;
+    }
+
+    match map
+        .blocks
+        .get(y as usize)
+        .and_then(|line| line.get(x as usize))
+    {
+        Some(&other) => other == tile || tile.is_solid() && other.def().slope.is_some(),
+        None => true,
+    }
+}
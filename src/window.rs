@@ -0,0 +1,374 @@
+use camera::Camera;
+use game::{Input, Player, Timestep, TIME_STEP};
+use gl;
+use map::Map;
+use options::{Options, View};
+use rect::Rect;
+use replay::Replay;
+use resources::*;
+use roots::Roots;
+use sdl2;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::video::SwapInterval;
+use sprite::Animator;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// the size of the view in tiles the levels are made for
+const VIEW_WIDTH: f64 = 16.0;
+const VIEW_HEIGHT: f64 = 9.0;
+
+/// The tiles of `map` that are at least partly inside of `view`.
+fn visible_tiles<'a>(view: &Rect, map: &'a Map) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let cols = view.x.floor().max(0.0) as usize..view.right().ceil().max(0.0) as usize;
+    let lines = view.y.floor().max(0.0) as usize..view.bottom().ceil().max(0.0) as usize;
+
+    (lines.start..lines.end.min(map.blocks.len())).flat_map(move |y| {
+        let line_len = map.blocks[y].len();
+        (cols.start..cols.end.min(line_len)).map(move |x| (x, y))
+    })
+}
+
+fn find_sdl_gl_driver() -> Option<u32> {
+    for (index, item) in sdl2::render::drivers().enumerate() {
+        if item.name == "opengl" {
+            return Some(index as u32);
+        }
+    }
+    None
+}
+
+/// Fits the view into a window with a drawable area of `width` x `height`
+/// pixels. Resizes the camera for `View::Extend` and returns the viewport as
+/// x, y, width, height in pixels.
+fn fit_view(width: u32, height: u32, view: View, camera: &mut Camera) -> (i32, i32, i32, i32) {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let aspect = VIEW_WIDTH / VIEW_HEIGHT;
+
+    match view {
+        View::Letterbox => {
+            camera.width = VIEW_WIDTH;
+            camera.height = VIEW_HEIGHT;
+
+            let (w, h) = if width / height > aspect {
+                (height * aspect, height)
+            } else {
+                (width, width / aspect)
+            };
+            let (x, y) = ((width - w) / 2.0, (height - h) / 2.0);
+            (x as i32, y as i32, w as i32, h as i32)
+        }
+        View::Extend => {
+            // never show less than the full 16x9 tiles
+            if width / height > aspect {
+                camera.width = VIEW_HEIGHT * width / height;
+                camera.height = VIEW_HEIGHT;
+            } else {
+                camera.width = VIEW_WIDTH;
+                camera.height = VIEW_WIDTH * height / width;
+            }
+            (0, 0, width as i32, height as i32)
+        }
+    }
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    if find_sdl_gl_driver().is_none() {
+        Err("Could not initialize opengl")?
+    }
+
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+
+    let gl_attr = video_subsystem.gl_attr();
+    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    gl_attr.set_context_version(3, 3);
+    gl_attr.set_context_flags().forward_compatible().set();
+
+    let mut builder = video_subsystem.window("xD", 711, 400);
+    builder.position_centered().opengl().resizable();
+    if options.fullscreen {
+        builder.fullscreen_desktop();
+    }
+    if options.borderless {
+        builder.borderless();
+    }
+    if options.highdpi {
+        builder.allow_highdpi();
+    }
+    let window = builder.build().map_err(|e| e.to_string())?;
+
+    let _ctx = window.gl_create_context().unwrap();
+
+    // the frame limiter below is only used when vsync isn't available
+    let vsync = video_subsystem
+        .gl_set_swap_interval(SwapInterval::VSync)
+        .is_ok();
+
+    gl::load_with(|name| video_subsystem.gl_get_proc_address(name) as *const _);
+    let mut renderer = Renderer::new();
+    // the blend function depends on the texture, see `Renderer::texture`
+    unsafe {
+        gl::Enable(gl::BLEND);
+    }
+
+    let mut content = Content::new(Roots::find(options)?);
+
+    let playback = match options.replay {
+        Some(ref path) => Some(Replay::load(path)?),
+        None => None,
+    };
+    let level_name = match playback {
+        Some(ref replay) => &replay.level,
+        None => &options.level,
+    };
+    let level = content.load::<Map>(level_name.clone())?;
+    let (spawn, time_step) = match playback {
+        Some(ref replay) => (replay.spawn, replay.time_step),
+        None => (content.get(&level).spawn, TIME_STEP),
+    };
+    let mut recording = Replay::new(time_step, level_name, spawn);
+    let mut step = 0;
+
+    let mut player = Player::new(spawn.0, spawn.1);
+    let mut input = Input::default();
+
+    let mut camera = Camera::new(VIEW_WIDTH, VIEW_HEIGHT);
+    let (width, height) = window.drawable_size();
+    let mut viewport = fit_view(width, height, options.view, &mut camera);
+    camera.jump_to(&player.rect, content.get(&level));
+
+    let block_shader = Shader::frag(&mut content, "shaders/block.frag")?;
+    let bg_shader = Shader::frag(&mut content, "shaders/bg.frag")?;
+    let sprite_shader = Shader::frag(&mut content, "shaders/sprite.frag")?;
+    // not from the content, so the broken shader frame can't break too
+    let mut plain_shader = Shader::plain()?;
+    let mut tiles = Tiles::new(&mut content, "tilesets/default.tileset")?;
+    let mut player_sprite = Sprite::new(&mut content, "sprites/player.sheet")?;
+    let mut player_animator = Animator::default();
+
+    // the game works without an audio device, just silently
+    let audio = match Audio::new(&sdl_context) {
+        Ok(audio) => Some(audio),
+        Err(err) => {
+            println!("No sound: {}", err);
+            None
+        }
+    };
+    let jump_sound = Sound::new(&mut content, "sounds/jump.wav")?;
+
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let mut timestep = Timestep::new(time_step);
+
+    let start = Instant::now();
+    let mut now = Instant::now();
+    'main_loop: loop {
+        let time_passed = (Instant::now() - now).as_secs_f64();
+        timestep.advance(time_passed);
+
+        now = Instant::now();
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    repeat: false,
+                    ..
+                } => break 'main_loop,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    // the drawable size differs from the window size on high-DPI displays
+                    let (width, height) = window.drawable_size();
+                    viewport = fit_view(width, height, options.view, &mut camera);
+                }
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => {
+                    // println!("+{}", key);
+                    if key == Keycode::A {
+                        input.is_left_down = true;
+                    } else if key == Keycode::D {
+                        input.is_right_down = true;
+                    } else if key == Keycode::S {
+                        input.is_down_down = true;
+                    } else if key == Keycode::Space {
+                        input.is_jump_press = true;
+                        input.is_jump_down = true;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } => {
+                    // println!("-{}", key);
+                    if key == Keycode::A {
+                        input.is_left_down = false;
+                    } else if key == Keycode::D {
+                        input.is_right_down = false;
+                    } else if key == Keycode::S {
+                        input.is_down_down = false;
+                    } else if key == Keycode::Space {
+                        input.is_jump_down = false;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // debug - update resources
+        content.update();
+        player_sprite.update(&mut content);
+        tiles.update(&mut content);
+
+        // physics
+        while timestep.next_step() {
+            // the keyboard takes over once the replay is done
+            let step_input = match playback {
+                Some(ref replay) if step < replay.inputs.len() => replay.inputs[step],
+                _ => input,
+            };
+            step += 1;
+
+            player.step(&step_input, time_step, content.get(&level));
+            recording.push(step_input);
+
+            if player.jumped {
+                if let Some(ref audio) = audio {
+                    audio.play(content.get(&jump_sound));
+                }
+            }
+
+            // cleanup
+            input.is_jump_press = false;
+        }
+
+        let player_rect = player.interpolated_rect(timestep.alpha());
+        camera.update(&player_rect, content.get(&level), time_passed);
+
+        player_animator.play(player.animation());
+        player_animator.update(time_passed);
+
+        // black bars around the view, then only draw inside of it
+        unsafe {
+            let (x, y, width, height) = viewport;
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::Viewport(x, y, width, height);
+            gl::Scissor(x, y, width, height);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+
+        // render level
+        renderer.begin(
+            &camera,
+            (viewport.2, viewport.3),
+            start.elapsed().as_secs_f64(),
+        );
+        renderer.clear(0.5, 0.5, 0.5);
+
+        let view = camera.view();
+        let bg = content.get_mut(&bg_shader);
+        // how much red, green and blue the background pattern has
+        bg.set("colors", Uniform::Vec3(0.3, 0.8, 0.7));
+        renderer.shader(bg);
+        renderer.rect2(view.x, view.y, view.width, view.height);
+
+        // the tiles that aren't in the tileset in their plain color
+        renderer.shader(content.get_mut(&block_shader));
+        let (map, tileset) = (content.get(&level), content.get(&tiles.tileset));
+        for (x, y) in visible_tiles(&view, map) {
+            let tile = map.blocks[y][x];
+            let def = tile.def();
+            match def.color {
+                Some((r, g, b)) if !tileset.has(tile) => {
+                    let (x, y) = (x as f64, y as f64);
+                    renderer.rgb(r, g, b);
+                    match def.slope {
+                        Some((left, right)) => renderer.polygon(&[
+                            (x, y + 1.0 - left),
+                            (x + 1.0, y + 1.0 - right),
+                            (x + 1.0, y + 1.0),
+                            (x, y + 1.0),
+                        ]),
+                        None => renderer.rect2(x, y, 1.0, 1.0),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        renderer.rgb(1.0, 1.0, 1.0);
+        renderer.shader(content.get_mut(&sprite_shader));
+        let (map, tileset) = (content.get(&level), content.get(&tiles.tileset));
+        let tiles_texture = content.get(&tiles.texture);
+        renderer.texture(tiles_texture);
+        for (x, y) in visible_tiles(&view, map) {
+            if let Some(quarters) = tileset.quarters(map, x, y) {
+                for (i, quarter) in quarters.iter().enumerate() {
+                    let rect = Rect::new(
+                        x as f64 + (i % 2) as f64 * 0.5,
+                        y as f64 + (i / 2) as f64 * 0.5,
+                        0.5,
+                        0.5,
+                    );
+                    renderer.rect_uv(&rect, tiles_texture.tex_coords(quarter, false));
+                }
+            }
+        }
+
+        //// render player
+        renderer.texture(content.get(&player_sprite.texture));
+
+        let frame = player_animator.frame(content.get(&player_sprite.sheet));
+        renderer.rect_uv(
+            &player_rect,
+            player_sprite.tex_coords(&content, frame, player.facing_left),
+        );
+
+        // a red frame while a shader is broken, the console says why
+        let shaders = [&bg_shader, &block_shader, &sprite_shader];
+        if cfg!(debug_assertions)
+            && shaders
+                .iter()
+                .any(|shader| content.get(*shader).error().is_some())
+        {
+            let border = 0.1;
+            renderer.rgb(0.9, 0.1, 0.1);
+            renderer.shader(&mut plain_shader);
+            renderer.rect2(view.x, view.y, view.width, border);
+            renderer.rect2(view.x, view.bottom() - border, view.width, border);
+            renderer.rect2(view.x, view.y, border, view.height);
+            renderer.rect2(view.right() - border, view.y, border, view.height);
+        }
+
+        renderer.flush();
+
+        window.gl_swap_window();
+
+        // let frame_time = Duration::from_micros(50000);
+        let frame_time = Duration::from_micros(6944);
+        // let frame_time = Duration::from_micros(16666);
+        // println!(
+        //     "{:?}",
+        //     frame_time - std::cmp::min(Instant::now() - now, frame_time)
+        // );
+        // Instant::now() - now);
+
+        if !vsync {
+            sleep(frame_time - std::cmp::min(Instant::now() - now, frame_time));
+        }
+        // std::thread::sleep(std::time::Duration::from_micros(16666));
+    }
+
+    if let Some(ref path) = options.record {
+        recording.save(path)?;
+        println!("Saved replay: {}", path.display());
+    }
+
+    Ok(())
+}